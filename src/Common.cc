@@ -147,150 +147,32 @@ void PrintColor(Color color, std::string str) {
 
 SimulationConfig Config::global_config;
 
-SimulationConfig initialize_config(json config) {
-    SimulationConfig parsed_config;
+static std::string to_string(sjq_rust::FfiStr str) {
+    return std::string(str.ptr, str.len);
+}
 
-    /* Core configs */
-    parsed_config.num_cores = config["num_cores"];
-    if ((std::string)config["core_type"] == "systolic_os")
-        parsed_config.core_type = CoreType::SYSTOLIC_OS;
-    else if ((std::string)config["core_type"] == "systolic_ws")
-        parsed_config.core_type = CoreType::SYSTOLIC_WS;
-    else
-        throw std::runtime_error(fmt::format("Not implemented core type {} ",
-                                             (std::string)config["core_type"]));
-    parsed_config.core_freq = config["core_freq"];
-    parsed_config.core_width = config["core_width"];
-    parsed_config.core_height = config["core_height"];
-
-    /* Vector configs */
-    parsed_config.process_bit = config["process_bit"];
-
-    parsed_config.vector_core_count = config["vector_core_count"];
-    parsed_config.vector_core_width = config["vector_core_width"];
-    parsed_config.add_latency = config["add_latency"];
-    parsed_config.mul_latency = config["mul_latency"];
-    parsed_config.exp_latency = config["exp_latency"];
-    parsed_config.gelu_latency = config["gelu_latency"];
-    parsed_config.add_tree_latency = config["add_tree_latency"];
-    parsed_config.scalar_sqrt_latency = config["scalar_sqrt_latency"];
-    parsed_config.scalar_add_latency = config["scalar_add_latency"];
-    parsed_config.scalar_mul_latency = config["scalar_mul_latency"];
-
-    /* SRAM configs */
-    parsed_config.sram_size = config["sram_size"];
-    parsed_config.sram_width = config["sram_width"];
-    parsed_config.spad_size = config["sram_size"];
-    parsed_config.accum_spad_size = config["sram_size"];
-
-    /* log config*/
+SimulationConfig SimulationConfig::from_rust(
+    const sjq_rust::SimulationConfig *config) {
+    SimulationConfig parsed_config;
+    static_cast<sjq_rust::SimulationConfigView &>(parsed_config) =
+        sjq_rust::get_config_view(config);
+    // both scratchpads span the whole SRAM, as they always have
+    parsed_config.spad_size = parsed_config.sram_size;
+    parsed_config.accum_spad_size = parsed_config.sram_size;
+
+    parsed_config.model_name = to_string(sjq_rust::config_model_name(config));
+    parsed_config.pim_config_path =
+        to_string(sjq_rust::config_pim_config_path(config));
     parsed_config.operation_log_output_path =
-        config["operation_log_output_path"];
-
-    /* Icnt config */
-    if ((std::string)config["icnt_type"] == "simple")
-        parsed_config.icnt_type = IcntType::SIMPLE;
-    else if ((std::string)config["icnt_type"] == "booksim2")
-        parsed_config.icnt_type = IcntType::BOOKSIM2;
-    else
-        throw std::runtime_error(fmt::format("Not implemented icnt type {} ",
-                                             (std::string)config["icnt_type"]));
-    parsed_config.icnt_freq = config["icnt_freq"];
-    if (config.contains("icnt_latency"))
-        parsed_config.icnt_latency = config["icnt_latency"];
-    if (config.contains("icnt_config_path"))
-        parsed_config.icnt_config_path = config["icnt_config_path"];
-
-    parsed_config.precision = config["precision"];
-    parsed_config.layout = config["layout"];
-    parsed_config.scheduler_type = config["scheduler"];
+        to_string(sjq_rust::config_operation_log_output_path(config));
+    parsed_config.log_dir = to_string(sjq_rust::config_log_dir(config));
+    parsed_config.request_dataset_path =
+        to_string(sjq_rust::config_request_dataset_path(config));
+    parsed_config.icnt_config_path =
+        to_string(sjq_rust::config_icnt_config_path(config));
     return parsed_config;
 }
 
-void initialize_memory_config(std::string mem_config_path) {
-    json mem_config = load_config(mem_config_path);
-    PrintColor(Color::RED, (std::string)mem_config["dram_type"]);
-    /* DRAM config */
-    if ((std::string)mem_config["dram_type"] == "dram")
-        Config::global_config.dram_type = DramType::DRAM;
-    else if ((std::string)mem_config["dram_type"] == "newton")
-        Config::global_config.dram_type = DramType::NEWTON;
-    else if ((std::string)mem_config["dram_type"] == "neupims")
-        Config::global_config.dram_type = DramType::NEUPIMS;
-    else
-        throw std::runtime_error(
-            fmt::format("Not implemented dram type {} ",
-                        (std::string)mem_config["dram_type"]));
-    Config::global_config.dram_freq = mem_config["dram_freq"];
-
-    Config::global_config.dram_channels = mem_config["dram_channels"];
-    if (mem_config.contains("dram_req_size"))
-        Config::global_config.dram_req_size = mem_config["dram_req_size"];
-
-    /* PIM config */
-    if (mem_config.contains("pim_config_path")) {
-        Config::global_config.pim_config_path = mem_config["pim_config_path"];
-        // DRAM row buffer size (in bytes)
-        Config::global_config.dram_page_size = mem_config["dram_page_size"];
-        Config::global_config.dram_banks_per_ch =
-            mem_config["dram_banks_per_ch"];
-        // # params per PIM_COMP command
-        Config::global_config.pim_comp_coverage =
-            mem_config["pim_comp_coverage"];
-    }
-
-    Config::global_config.HBM_size = (uint64_t)(mem_config["HBM_size"])GB;
-    Config::global_config.HBM_act_buf_size =
-        (uint64_t)(mem_config["HBM_act_buf_size"])MB;
-}
-
-void initialize_client_config(std::string cli_config_path) {
-    Config::global_config.request_dataset_path = cli_config_path;
-
-    // json cli_config = load_config(cli_config_path);
-    // /* Client config */
-    // Config::global_config.request_dataset_path =
-    // cli_config["request_dataset_path"];
-    // Config::global_config.request_input_seq_len =
-    // cli_config["request_input_seq_len"];
-    // Config::global_config.request_interval = cli_config["request_interval"];
-    // Config::global_config.request_total_cnt =
-    // cli_config["request_total_cnt"];
-}
-
-void initialize_model_config(std::string model_config_path) {
-    json model_config = load_config(model_config_path);
-    /* GPT configs */
-    Config::global_config.model_name = model_config["model_name"];
-    Config::global_config.model_params_b = model_config["model_params_b"];
-    Config::global_config.model_vocab_size = model_config["model_vocab_size"];
-    Config::global_config.model_n_layer = model_config["model_n_layer"];
-    Config::global_config.model_n_head = model_config["model_n_head"];
-    Config::global_config.model_n_embd = model_config["model_n_embd"];
-    /* parallelism config */
-    Config::global_config.n_tp = model_config["n_tp"];
-}
-void initialize_system_config(std::string sys_config_path) {
-    json sys_config = load_config(sys_config_path);
-    /* Batch configs */
-    if ((std::string)sys_config["run_mode"] == "npu")
-        Config::global_config.run_mode = RunMode::NPU_ONLY;
-    else if ((std::string)sys_config["run_mode"] == "npu+pim")
-        Config::global_config.run_mode = RunMode::NPU_PIM;
-    else
-        Config::global_config.run_mode = RunMode::NPU_ONLY;
-
-    Config::global_config.ch_load_balancing = sys_config["ch_load_balancing"];
-
-    Config::global_config.kernel_fusion = sys_config["kernel_fusion"];
-
-    Config::global_config.max_seq_len = sys_config["max_seq_len"];
-    Config::global_config.max_active_reqs = sys_config["max_active_reqs"];
-    Config::global_config.max_batch_size = sys_config["max_batch_size"];
-
-    Config::global_config.sub_batch_mode = sys_config["sub_batch_mode"];
-}
-
 json load_config(std::string config_path) {
     json config_json;
     std::ifstream config_file(config_path);
@@ -580,6 +462,19 @@ uint64_t AddressConfig::encode_pim_comps_readres(int ch, int row, int num_comps,
     return make_address(ch, rank, bankgroup, bank, row, col);
 }
 
+// the number of COMPs of an address made by encode_pim_comps_readres
+int AddressConfig::decode_pim_comps_readres(addr_type addr) {
+    int ra_bits = 1;
+    int bg_bits = 2;
+    int ba_bits = 2;
+    int channel_bits = LogBase2(Config::global_config.dram_channels);
+    int col_bits = 4;
+    int offset = 6;
+
+    addr >>= offset + col_bits + channel_bits;
+    return (addr & ((1 << (ra_bits + bg_bits + ba_bits)) - 1)) + 1;
+}
+
 // used for sub-batch interleaving
 std::string stageToString(Stage stage) {
     static const std::map<Stage, std::string> stageMap = {
@@ -69,6 +69,7 @@ uint64_t encode_pim_header(int channel, int row, bool for_gwrite, int num_comps,
                            int num_readres);
 uint64_t encode_pim_comps_readres(int ch, int row, int num_comps,
                                   bool last_cmd);
+int decode_pim_comps_readres(addr_type addr);
 
 addr_type switch_co_ch(addr_type addr);
 }  // namespace AddressConfig
@@ -232,11 +233,6 @@ typedef struct MemoryAccess {
 uint32_t generate_id();
 uint32_t generate_mem_access_id();
 json load_config(std::string config_path);
-SimulationConfig initialize_config(json config);  // npu config
-void initialize_memory_config(std::string mem_config_path);
-void initialize_client_config(std::string cli_config_path);
-void initialize_model_config(std::string model_config_path);
-void initialize_system_config(std::string sys_config_path);
 
 std::string to_hex(uint32_t input);
 template <typename... Args>
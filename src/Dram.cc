@@ -96,8 +96,68 @@ bool PIM::is_full(uint32_t cid, MemoryAccess *request) {
     return full;
 }
 
+// count DRAM reads and writes per channel, PIM commands are not tracked
+static bool channel_mem_op(MemoryAccess *request, sjq_rust::MemOp &op) {
+    switch (request->req_type) {
+        case MemoryAccessType::READ:
+            op = sjq_rust::MemOp::Load;
+            return true;
+        case MemoryAccessType::WRITE:
+            op = sjq_rust::MemOp::Store;
+            return true;
+        default:
+            return false;
+    }
+}
+
+// count the hardware operations of a request for the energy estimation and
+// the roofline analysis, attributed to the operation of its tile
+static void count_hw_ops(MemoryAccess *request) {
+    auto tile = request->parent_tile.lock();
+    const char *optype = tile ? tile->optype.c_str() : nullptr;
+    auto add = [optype](sjq_rust::HwOp op, uint64_t n) {
+        sjq_rust::add_hw_ops(global_counts_ctx, op, n, optype);
+    };
+    switch (request->req_type) {
+        case MemoryAccessType::READ:
+            add(sjq_rust::HwOp::DramRead, 1);
+            add(sjq_rust::HwOp::SramAccess, 1);
+            break;
+        case MemoryAccessType::WRITE:
+            add(sjq_rust::HwOp::DramWrite, 1);
+            add(sjq_rust::HwOp::SramAccess, 1);
+            break;
+        case MemoryAccessType::GWRITE:
+            add(sjq_rust::HwOp::DramWrite, 1);
+            break;
+        case MemoryAccessType::COMP:
+            add(sjq_rust::HwOp::PimComp, 1);
+            break;
+        case MemoryAccessType::READRES:
+            add(sjq_rust::HwOp::SramAccess, 1);
+            break;
+        case MemoryAccessType::P_HEADER:
+            add(sjq_rust::HwOp::PimHeader, 1);
+            break;
+        case MemoryAccessType::COMPS_READRES:
+            add(sjq_rust::HwOp::PimComp,
+                AddressConfig::decode_pim_comps_readres(
+                    request->dram_address));
+            add(sjq_rust::HwOp::SramAccess, 1);
+            break;
+        default:
+            break;
+    }
+}
+
 void PIM::push(uint32_t cid, MemoryAccess *request) {
     uint32_t mem_ch = get_channel_id(request);
+    count_hw_ops(request);
+
+    sjq_rust::MemOp op;
+    if (channel_mem_op(request, op)) {
+        sjq_rust::add_channel_ops(global_counts_ctx, mem_ch, op, 1, _cycles);
+    }
 
     auto settings = sjq_rust::get_settings();
     if (settings->fast_read && request->req_type == MemoryAccessType::READ) {
@@ -186,6 +246,13 @@ void PIM::pop(uint32_t cid) {
     // make sure update stat before mem-pop
     update_stat(cid);
 
+    sjq_rust::MemOp op;
+    if (channel_mem_op(top(cid), op) &&
+        !sjq_rust::reduce_channel_ops(global_counts_ctx, cid, op, 1, _cycles)) {
+        spdlog::error("reduce_channel_ops failed");
+        throw std::runtime_error("reduce_channel_ops failed");
+    }
+
     assert(!is_empty(cid));
     if (fast_ret_queue[cid].empty()) {
         _mem->Pop(cid);
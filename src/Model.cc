@@ -67,6 +67,13 @@ pipeline parallelism in attention is applied to column(QKVgen, fc1) and row(proj
 need for layernorm variable to be at all chip
 */
 void Model::init_params() {
+    uint32_t d_k = _config.model_n_embd / _config.model_n_head;
+    // Q plus one K and V per KV head, which is fewer than Q heads with GQA/MQA
+    uint32_t qkv_width = _config.model_n_embd + 2 * _config.model_n_kv_head * d_k;
+    // Gated activations fuse the gate and up projections into FC1
+    uint32_t fc1_width = _config.model_activation == Activation::SWIGLU
+                             ? 2 * _config.model_ffn_hidden
+                             : _config.model_ffn_hidden;
     for (int i = 0; i < _config.model_n_layer; ++i) {
         auto attn = name_gen(LAYER(i), BlockType::Attention);
         create_weight(name_gen(attn, OperationType::LayerNorm, ParameterType::Weight),
@@ -74,9 +81,9 @@ void Model::init_params() {
         create_weight(name_gen(attn, OperationType::LayerNorm, ParameterType::Bias),
                       {_config.model_n_embd});
         create_weight(name_gen(attn, OperationType::QKVGen, ParameterType::Weight),
-                      {_config.model_n_embd, 3 * _config.model_n_embd / _config.n_tp});
+                      {_config.model_n_embd, qkv_width / _config.n_tp});
         create_weight(name_gen(attn, OperationType::QKVGen, ParameterType::Bias),
-                      {3 * _config.model_n_embd / _config.n_tp});
+                      {qkv_width / _config.n_tp});
         create_weight(name_gen(attn, OperationType::Projection, ParameterType::Weight),
                       {_config.model_n_embd / _config.n_tp, _config.model_n_embd});
         create_weight(name_gen(attn, OperationType::Projection, ParameterType::Bias),
@@ -88,11 +95,11 @@ void Model::init_params() {
         create_weight(name_gen(ffn, OperationType::LayerNorm, ParameterType::Bias),
                       {_config.model_n_embd});
         create_weight(name_gen(ffn, OperationType::FullyConnected1, ParameterType::Weight),
-                      {_config.model_n_embd, 4 * _config.model_n_embd / _config.n_tp});
+                      {_config.model_n_embd, fc1_width / _config.n_tp});
         create_weight(name_gen(ffn, OperationType::FullyConnected1, ParameterType::Bias),
-                      {4 * _config.model_n_embd / _config.n_tp});
+                      {fc1_width / _config.n_tp});
         create_weight(name_gen(ffn, OperationType::FullyConnected2, ParameterType::Weight),
-                      {4 * _config.model_n_embd / _config.n_tp, _config.model_n_embd});
+                      {_config.model_ffn_hidden / _config.n_tp, _config.model_n_embd});
         create_weight(name_gen(ffn, OperationType::FullyConnected2, ParameterType::Bias),
                       {_config.model_n_embd});
     }
@@ -124,10 +131,10 @@ std::shared_ptr<Tensor> Model::load_cache(uint32_t layer, std::string type) {
     std::vector<uint32_t> shape;
     if (type == "key") {
         shape.assign(
-            {_config.model_n_head, _config.model_n_embd / _config.model_n_head, _num_token});
+            {_config.model_n_kv_head, _config.model_n_embd / _config.model_n_head, _num_token});
     } else if (type == "value") {
         shape.assign(
-            {_config.model_n_head, _num_token, _config.model_n_embd / _config.model_n_head});
+            {_config.model_n_kv_head, _num_token, _config.model_n_embd / _config.model_n_head});
     }
 
     return create_tensor("layer" + std::to_string(layer) + "." + type, shape);
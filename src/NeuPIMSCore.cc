@@ -172,7 +172,8 @@ void NeuPIMSCore::issue(Tile &in_tile) {
                 // Increment the remaining loads counter and push the
                 // instruction to the load instruction queue
                 tile->remaining_loads++;
-                sjq_rust::add_loads(global_counts_ctx, 1, _core_cycle);
+                sjq_rust::add_core_ops(global_counts_ctx, _id,
+                                       sjq_rust::MemOp::Load, 1, _core_cycle);
                 _ld_inst_queue_for_sa.push(inst);
             } else {
                 // Log failure to allocate space in the buffer and assert
@@ -190,14 +191,17 @@ void NeuPIMSCore::issue(Tile &in_tile) {
                    inst.opcode == Opcode::MOVOUT_POOL) {
             // Handle MOVOUT and MOVOUT_POOL opcodes
             tile->remaining_accum_io++;
-            sjq_rust::add_stores(global_counts_ctx, 1, _core_cycle);
+            sjq_rust::add_core_ops(global_counts_ctx, _id,
+                                   sjq_rust::MemOp::Store, 1, _core_cycle);
             _st_inst_queue_for_sa.push(inst);
         } else {
             // Handle other opcodes
             tile->remaining_accum_io++;
-            sjq_rust::add_stores(global_counts_ctx, 1, _core_cycle);
+            sjq_rust::add_core_ops(global_counts_ctx, _id,
+                                   sjq_rust::MemOp::Store, 1, _core_cycle);
             tile->remaining_computes++;
-            sjq_rust::add_computes(global_counts_ctx, 1);
+            sjq_rust::add_core_ops(global_counts_ctx, _id,
+                                   sjq_rust::MemOp::Compute, 1, _core_cycle);
             _ex_inst_queue_for_sa.push(inst);
         }
     }
@@ -293,6 +297,9 @@ Ptr<Tile> NeuPIMSCore::pop_finished_tile() {
 
     auto result = _finished_tiles.front();
     result->stat.end_cycle = _core_cycle;
+    sjq_rust::add_operation_cycles(
+        global_counts_ctx, result->optype.c_str(),
+        result->stat.end_cycle - result->stat.start_cycle);
     _finished_tiles.pop();
     return result;
 }
@@ -437,8 +444,9 @@ void NeuPIMSCore::push_memory_response(MemoryAccess *response) {
             tile->remaining_accum_io--;
             // fix here, only systolic array need this
             if (!is_pim) {
-                if (!sjq_rust::reduce_stores(global_counts_ctx, 1,
-                                             _core_cycle)) {
+                if (!sjq_rust::reduce_core_ops(global_counts_ctx, _id,
+                                               sjq_rust::MemOp::Store, 1,
+                                               _core_cycle)) {
                     spdlog::error("reduce_stores failed");
                     throw std::runtime_error("reduce_stores failed");
                 }
@@ -446,8 +454,9 @@ void NeuPIMSCore::push_memory_response(MemoryAccess *response) {
 
         } else {
             if (!is_pim) {
-                if (!sjq_rust::reduce_loads(global_counts_ctx, 1,
-                                            _core_cycle)) {
+                if (!sjq_rust::reduce_core_ops(global_counts_ctx, _id,
+                                               sjq_rust::MemOp::Load, 1,
+                                               _core_cycle)) {
                     spdlog::error("reduce_loads failed");
                     throw std::runtime_error("reduce_loads failed");
                 }
@@ -499,7 +508,8 @@ void NeuPIMSCore::pim_push_memory_response(MemoryAccess *response) {
                 total_tiles += _tile->remaining_loads;
             }
             spdlog::info("PIM total_tiles: {}, get_loads: {}", total_tiles,
-                         sjq_rust::get_loads(global_counts_ctx));
+                         sjq_rust::get_core_ops(global_counts_ctx, _id,
+                                                sjq_rust::MemOp::Load));
         }
     } else {
         assert(0);
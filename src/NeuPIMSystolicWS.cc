@@ -54,14 +54,17 @@ void NeuPIMSystolicWS::systolic_cycle() {
             bool is_pim = tile->stage_platform == StagePlatform::PIM;
             tile->remaining_accum_io--;
             if (!is_pim)
-                if (!sjq_rust::reduce_stores(global_counts_ctx, 1,
-                                             _core_cycle)) {
+                if (!sjq_rust::reduce_core_ops(global_counts_ctx, _id,
+                                               sjq_rust::MemOp::Store, 1,
+                                               _core_cycle)) {
                     spdlog::error("reduce_stores failed");
                     throw std::runtime_error("reduce_stores failed");
                 }
             tile->remaining_computes--;
             if (!is_pim)
-                if (!sjq_rust::reduce_computes(global_counts_ctx, 1)) {
+                if (!sjq_rust::reduce_core_ops(global_counts_ctx, _id,
+                                               sjq_rust::MemOp::Compute, 1,
+                                               _core_cycle)) {
                     spdlog::error("reduce_computes failed");
                     throw std::runtime_error("reduce_computes failed");
                 }
@@ -98,14 +101,17 @@ void NeuPIMSystolicWS::vector_unit_cycle() {
                 auto is_pim = tile->stage_platform == StagePlatform::PIM;
                 tile->remaining_accum_io--;
                 if (!is_pim)
-                    if (!sjq_rust::reduce_stores(global_counts_ctx, 1,
-                                                 _core_cycle)) {
+                    if (!sjq_rust::reduce_core_ops(global_counts_ctx, _id,
+                                                   sjq_rust::MemOp::Store, 1,
+                                                   _core_cycle)) {
                         spdlog::error("reduce_stores failed");
                         throw std::runtime_error("reduce_stores failed");
                     }
                 tile->remaining_computes--;
                 if (!is_pim)
-                    if (!sjq_rust::reduce_computes(global_counts_ctx, 1)) {
+                    if (!sjq_rust::reduce_core_ops(global_counts_ctx, _id,
+                                                   sjq_rust::MemOp::Compute, 1,
+                                                   _core_cycle)) {
                         spdlog::error("reduce_computes failed");
                         throw std::runtime_error("reduce_computes failed");
                     }
@@ -150,8 +156,9 @@ void NeuPIMSystolicWS::ld_queue_cycle() {
             if (auto tile = front.parent_tile.lock()) {
                 assert(accesses.size() > 0);
                 tile->remaining_loads += accesses.size() - 1;
-                sjq_rust::add_loads(global_counts_ctx, accesses.size() - 1,
-                                    _core_cycle);
+                sjq_rust::add_core_ops(global_counts_ctx, _id,
+                                       sjq_rust::MemOp::Load,
+                                       accesses.size() - 1, _core_cycle);
                 tile->stat.memory_reads +=
                     accesses.size() * AddressConfig::alignment;
             } else {
@@ -267,8 +274,9 @@ void NeuPIMSystolicWS::st_queue_cycle() {
             if (auto tile = front.parent_tile.lock()) {
                 assert(accesses.size() > 0);
                 tile->remaining_accum_io += accesses.size() - 1;
-                sjq_rust::add_stores(global_counts_ctx, accesses.size() - 1,
-                                     _core_cycle);
+                sjq_rust::add_core_ops(global_counts_ctx, _id,
+                                       sjq_rust::MemOp::Store,
+                                       accesses.size() - 1, _core_cycle);
                 tile->stat.memory_writes +=
                     accesses.size() * AddressConfig::alignment;
             } else {
@@ -495,6 +503,9 @@ void NeuPIMSystolicWS::issue_ex_inst(Instruction inst) {
         // inst.repr());
         parent_tile->stat.num_calculation +=
             inst.tile_m * inst.tile_n * inst.tile_k;
+        sjq_rust::add_hw_ops(global_counts_ctx, sjq_rust::HwOp::SystolicMac,
+                             (uint64_t)inst.tile_m * inst.tile_n * inst.tile_k,
+                             parent_tile->optype.c_str());
 
         if (inst.opcode == Opcode::GEMM_PRELOAD) {
             _stat_systolic_preload_issue_count++;
@@ -45,15 +45,16 @@ Simulator::Simulator(SimulationConfig config)
     _cores.resize(config.num_cores);
     _n_cores = config.num_cores;
     _n_memories = config.dram_channels;
+    sjq_rust::register_units(global_counts_ctx, _n_cores, _n_memories);
     for (unsigned core_index = 0; core_index < _n_cores; core_index++) {
         spdlog::info("initializing NeuPIM SystolicWS cores.");
         _cores[core_index] =
             std::make_unique<NeuPIMSystolicWS>(core_index, _config);
     }
 
-    if (config.scheduler_type == "simple") {
+    if (config.scheduler_type == SchedulerType::SIMPLE) {
         _scheduler = std::make_unique<OrcaScheduler>(_config, &_core_cycles);
-    } else if (config.scheduler_type == "neupims") {
+    } else if (config.scheduler_type == SchedulerType::NEUPIMS) {
         _scheduler = std::make_unique<NeuPIMScheduler>(_config, &_core_cycles);
     }
 
@@ -214,6 +215,9 @@ void Simulator::cycle() {
                         front->core_id = core_id;
                         if (!_icnt->is_full(core_ind, front)) {
                             _icnt->push(core_ind, get_dest_node(front), front);
+                            sjq_rust::add_hw_ops(global_counts_ctx,
+                                                 sjq_rust::HwOp::IcntFlit, 1,
+                                                 nullptr);
                             _cores[core_id]->pop_memory_request1(channel_index);
                         }
                     }
@@ -224,6 +228,9 @@ void Simulator::cycle() {
                         front->core_id = core_id;
                         if (!_icnt->is_full(core_ind, front)) {
                             _icnt->push(core_ind, get_dest_node(front), front);
+                            sjq_rust::add_hw_ops(global_counts_ctx,
+                                                 sjq_rust::HwOp::IcntFlit, 1,
+                                                 nullptr);
                             _cores[core_id]->pop_memory_request2(channel_index);
                         }
                     }
@@ -264,6 +271,8 @@ void Simulator::cycle() {
                     !_icnt->is_full(mem_ind, _dram->top(dram_ind))) {
                     _icnt->push(mem_ind, get_dest_node(_dram->top(dram_ind)),
                                 _dram->top(dram_ind));
+                    sjq_rust::add_hw_ops(global_counts_ctx,
+                                         sjq_rust::HwOp::IcntFlit, 1, nullptr);
                     _dram->pop(dram_ind);
                 }
             }
@@ -6,7 +6,7 @@ ActAlloc::ActAlloc()
 void ActAlloc::init(addr_type base_addr) {
   _base_addr = base_addr;
   _top_addr = base_addr;
-  _act_buf_size = Config::global_config.HBM_act_buf_size;
+  _act_buf_size = Config::global_config.hbm_act_buf_size;
   _act_buf_limit = _base_addr + _act_buf_size;
 
   SPDLOG_INFO("ActAlloc init: base_addr {}, act_buf_size {}, _act_buf_limit {}",
@@ -31,7 +31,7 @@ void KVCacheAlloc::init_npu_layout(addr_type base_addr) {
     uint32_t max_active_reqs = Config::global_config.max_active_reqs;
     uint32_t max_seq_len = Config::global_config.max_seq_len;
     uint32_t h =
-        Config::global_config.model_n_head / Config::global_config.n_tp;
+        Config::global_config.model_n_kv_head / Config::global_config.n_tp;
     uint32_t d_k =
         Config::global_config.model_n_embd / Config::global_config.model_n_head;
     uint32_t precision = Config::global_config.precision;
@@ -39,7 +39,7 @@ void KVCacheAlloc::init_npu_layout(addr_type base_addr) {
     _base_addr = base_addr;
     _kv_cache_entry_size = 32;  // allocate once per seq_len 32
     _kv_cache_size = max_active_reqs * max_seq_len * h * d_k * precision;
-    ast(_base_addr + _kv_cache_size < Config::global_config.HBM_size);
+    ast(_base_addr + _kv_cache_size < Config::global_config.hbm_size);
 
     addr_type next_addr = _base_addr;
     // The number of sequence lengths that can be stored per block / sequence
@@ -12,7 +12,6 @@ int main(int argc, char **argv) {
     // init_settings();
     // auto v = get_settings();
     // spdlog::info("fast_read: {}", v->fast_read);
-    global_counts_ctx = sjq_rust::new_global_counts_ctx();
     CommandLineParser cmd_parser = CommandLineParser();
     cmd_parser.add_command_line_option<std::string>("sjqconfig",
                                                     "sjq config file");
@@ -28,6 +27,8 @@ int main(int argc, char **argv) {
         "sys_config", "Path for system configuration file");
     cmd_parser.add_command_line_option<std::string>(
         "log_dir", "Path for experiment result log directory");
+    cmd_parser.add_command_line_multitoken_option<std::vector<std::string>>(
+        "set", "Override config fields, e.g. --set max_batch_size=256");
 
     cmd_parser.add_command_line_option<std::string>(
         "models_list", "Path for the models list file");
@@ -35,6 +36,10 @@ int main(int argc, char **argv) {
         "log_level", "Set for log level [trace, debug, info], default = info");
     cmd_parser.add_command_line_option<std::string>(
         "mode", "choose one_model or two_model");
+    cmd_parser.add_command_line_option<std::string>(
+        "counts_format",
+        "Format of the counts report [json, json_compact, toml, csv], "
+        "default = json");
 
     try {
         cmd_parser.parse(argc, argv);
@@ -46,10 +51,12 @@ int main(int argc, char **argv) {
     }
     std::string sjq_config_path;
     cmd_parser.set_if_defined("sjqconfig", &sjq_config_path);
-    if (!sjq_config_path.empty()) {
-        sjq_rust::init_settings_with_file(sjq_config_path.c_str());
-    } else {
-        sjq_rust::init_settings();
+    bool settings_loaded =
+        sjq_config_path.empty()
+            ? sjq_rust::init_settings()
+            : sjq_rust::init_settings_with_file(sjq_config_path.c_str());
+    if (!settings_loaded) {
+        throw std::runtime_error("Failed to load the settings");
     }
 
     std::string model_base_path = "./models";
@@ -62,15 +69,22 @@ int main(int argc, char **argv) {
     else if (level == "info")
         spdlog::set_level(spdlog::level::info);
 
+    std::string counts_format = "json";
+    cmd_parser.set_if_defined("counts_format", &counts_format);
+    sjq_rust::ReportFormat report_format;
+    if (counts_format == "json")
+        report_format = sjq_rust::ReportFormat::JsonPretty;
+    else if (counts_format == "json_compact")
+        report_format = sjq_rust::ReportFormat::JsonCompact;
+    else if (counts_format == "toml")
+        report_format = sjq_rust::ReportFormat::Toml;
+    else if (counts_format == "csv")
+        report_format = sjq_rust::ReportFormat::Csv;
+    else
+        throw std::runtime_error("Unknown counts format: " + counts_format);
+
     std::string config_path;
     cmd_parser.set_if_defined("config", &config_path);
-
-    json config_json;
-    std::ifstream config_file(config_path);
-    config_file >> config_json;
-    config_file.close();
-    Config::global_config = initialize_config(config_json);
-
     std::string mem_config_path;
     cmd_parser.set_if_defined("mem_config", &mem_config_path);
     std::string cli_config_path;
@@ -82,11 +96,41 @@ int main(int argc, char **argv) {
     std::string log_dir_path;
     cmd_parser.set_if_defined("log_dir", &log_dir_path);
 
-    initialize_memory_config(mem_config_path);
-    initialize_client_config(cli_config_path);
-    initialize_model_config(model_config_path);
-    initialize_system_config(sys_config_path);
+    std::vector<std::string> config_sets;
+    cmd_parser.set_if_defined("set", &config_sets);
+    std::vector<const char *> config_set_ptrs;
+    for (auto &config_set : config_sets) {
+        config_set_ptrs.push_back(config_set.c_str());
+    }
 
+    sjq_rust::ConfigPaths config_paths = {
+        config_path.c_str(),       mem_config_path.c_str(),
+        model_config_path.c_str(), sys_config_path.c_str(),
+        cli_config_path.c_str(),   nullptr,
+        config_set_ptrs.data(),    config_set_ptrs.size()};
+    if (!sjq_rust::init_config_with_files(&config_paths)) {
+        throw std::runtime_error("Failed to load the rust side config");
+    }
+    auto validation_report = sjq_rust::validate_config();
+    auto num_config_errors =
+        sjq_rust::validation_error_count(validation_report);
+    for (size_t i = 0; i < num_config_errors; i++) {
+        spdlog::error("Invalid config: {}",
+                      sjq_rust::validation_error_message(validation_report, i));
+    }
+    sjq_rust::drop_validation_report(validation_report);
+    if (num_config_errors > 0) {
+        throw std::runtime_error("Invalid configuration");
+    }
+    // after the settings and the config, which hold the event log filter
+    // and the fallback output directory
+    global_counts_ctx = sjq_rust::new_global_counts_ctx(log_dir_path.c_str());
+    if (global_counts_ctx == nullptr) {
+        throw std::runtime_error("Failed to create the output directory");
+    }
+
+    Config::global_config =
+        SimulationConfig::from_rust(sjq_rust::get_global_config());
     Config::global_config.log_dir = log_dir_path;
 
     Operation::initialize(Config::global_config);
@@ -125,7 +169,14 @@ int main(int argc, char **argv) {
     simulator->launch_model(model);
     spdlog::info("Launch model: {}", model_name);
     simulator->run(model_name);
-    sjq_rust::save_global_counts_to_file(global_counts_ctx);
+    // each saver logs its own error, so keep writing the remaining reports
+    bool reports_saved = sjq_rust::save_global_counts_as(
+        global_counts_ctx, nullptr, nullptr, report_format);
+    reports_saved &= sjq_rust::save_trace_to_file(global_counts_ctx);
+    reports_saved &= sjq_rust::save_overlap_report(global_counts_ctx);
+    reports_saved &= sjq_rust::save_timeseries_to_file(global_counts_ctx);
+    reports_saved &= sjq_rust::save_energy_report(global_counts_ctx);
+    reports_saved &= sjq_rust::save_roofline_report(global_counts_ctx);
     sjq_rust::drop_global_counts_ctx(global_counts_ctx);
     MemoryAccess::log_count();
 
@@ -139,5 +190,9 @@ int main(int argc, char **argv) {
                      ? "NPU-only"
                      : "NPU+PIM",
                  "\033[0m");
+    if (!reports_saved) {
+        spdlog::error("Failed to write some of the reports");
+        return 1;
+    }
     return 0;
 }
@@ -8,12 +8,12 @@ Operation::Operation(MappingTable mapping_table) {
     _id = generate_id();
     _finish = false;
     spdlog::trace("Node {} op_type {}", _name.c_str(), _optype.c_str());
-    if (_config.layout == "NCHW") {
+    if (_config.layout == Layout::NCHW) {
         Ndim = 0;
         Cdim = 1;
         Hdim = 2;
         Wdim = 3;
-    } else if (_config.layout == "NHWC") {
+    } else if (_config.layout == Layout::NHWC) {
         Ndim = 0;
         Cdim = 3;
         Hdim = 1;
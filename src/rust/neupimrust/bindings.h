@@ -15,6 +15,59 @@
 
 namespace sjq_rust {
 
+/// The version of the counts report layout, increased on every incompatible change.
+static const uint32_t COUNTS_SCHEMA_VERSION = 1;
+
+/// The activation function of the feed-forward block.
+enum class Activation {
+  GELU,
+  RELU,
+  SWIGLU,
+};
+
+/// The dataflow of the systolic arrays, looked up by name in its registry.
+enum class CoreType {
+  SYSTOLIC_OS,
+  SYSTOLIC_WS,
+};
+
+/// The memory type, looked up by name in its registry.
+enum class DramType {
+  DRAM,
+  NEWTON,
+  NEUPIMS,
+};
+
+/// Hardware operations counted per stage.
+enum class HwOp {
+  /// A DRAM read request of `dram_req_size` bytes.
+  DramRead,
+  /// A DRAM write request of `dram_req_size` bytes, including PIM global buffer writes.
+  DramWrite,
+  /// A PIM header, which opens a row in every bank of the channel.
+  PimHeader,
+  /// A PIM COMP command, `pim_comp_coverage` MACs in every bank of the channel.
+  PimComp,
+  /// A transfer of `dram_req_size` bytes into or out of a scratchpad.
+  SramAccess,
+  /// A multiply-accumulate of the systolic array.
+  SystolicMac,
+  /// A packet through the interconnect.
+  IcntFlit,
+};
+
+/// The interconnect model, looked up by name in its registry.
+enum class IcntType {
+  SIMPLE,
+  BOOKSIM2,
+};
+
+/// The activation tensor layout, looked up by name in its registry.
+enum class Layout {
+  NCHW,
+  NHWC,
+};
+
 enum class LogLevel {
   Debug,
   Info,
@@ -22,6 +75,30 @@ enum class LogLevel {
   Error,
 };
 
+/// The resources tracked by [`GlobalCountsCtx::activity`].
+enum class MemOp {
+  Load,
+  Store,
+  Compute,
+  /// Busy while a load or a store is ongoing.
+  LoadOrStore,
+};
+
+/// The file format of the counts report.
+enum class ReportFormat {
+  JsonPretty,
+  JsonCompact,
+  Toml,
+  /// `<stem>_summary.csv` and `<stem>_histograms.csv`.
+  Csv,
+};
+
+/// Where attention runs, looked up by name in its registry.
+enum class RunMode {
+  NPU_ONLY,
+  NPU_PIM,
+};
+
 /// Run stages
 enum class RunStage {
   A,
@@ -33,29 +110,164 @@ enum class RunStage {
   Finished,
 };
 
+/// The request scheduling policy, looked up by name in its registry.
+enum class SchedulerType {
+  SIMPLE,
+  NEUPIMS,
+};
+
 struct GlobalCountsCtx;
 
+/// The configuration of one simulation, merged from the hardware, memory, model, system and
+/// client config files.
+///
+/// Only fields with a meaningful default may be left out; every other field has to be set by one
+/// of the files, so a forgotten hardware parameter is an error rather than a silent 0.
+struct SimulationConfig;
+
+/// The result of validating the global config, handed to the C++ side.
+struct ValidationReport;
+
+/// A borrowed, not nul-terminated UTF-8 string.
+///
+/// Valid as long as the config it was read from; build a `std::string(ptr, len)` to keep it.
+struct FfiStr {
+  const char *ptr;
+  size_t len;
+};
+
 struct NoIcnt {
   size_t total_packages;
 };
 
+/// All non-string fields of [`SimulationConfig`].
+struct SimulationConfigView {
+  uint32_t model_params_b;
+  uint32_t model_block_size;
+  uint32_t model_vocab_size;
+  uint32_t model_n_layer;
+  uint32_t model_n_head;
+  uint32_t model_n_embd;
+  uint32_t model_n_kv_head;
+  uint32_t model_ffn_hidden;
+  Activation model_activation;
+  bool model_tied_embeddings;
+  RunMode run_mode;
+  bool sub_batch_mode;
+  bool ch_load_balancing;
+  bool kernel_fusion;
+  uint32_t max_batch_size;
+  uint32_t max_active_reqs;
+  uint32_t max_seq_len;
+  uint64_t hbm_size;
+  uint64_t hbm_act_buf_size;
+  uint32_t num_cores;
+  CoreType core_type;
+  uint32_t core_freq;
+  uint32_t core_width;
+  uint32_t core_height;
+  uint32_t n_tp;
+  uint32_t n_pp;
+  uint32_t vector_core_count;
+  uint32_t vector_core_width;
+  uint32_t process_bit;
+  size_t layernorm_latency;
+  size_t softmax_latency;
+  size_t add_latency;
+  size_t mul_latency;
+  size_t exp_latency;
+  size_t gelu_latency;
+  size_t add_tree_latency;
+  size_t scalar_sqrt_latency;
+  size_t scalar_add_latency;
+  size_t scalar_mul_latency;
+  uint32_t sram_width;
+  uint32_t sram_size;
+  uint32_t spad_size;
+  uint32_t accum_spad_size;
+  DramType dram_type;
+  uint32_t dram_freq;
+  uint32_t dram_channels;
+  uint32_t dram_req_size;
+  uint32_t dram_page_size;
+  uint32_t dram_banks_per_ch;
+  uint32_t pim_comp_coverage;
+  uint32_t request_input_seq_len;
+  uint32_t request_interval;
+  uint32_t request_total_cnt;
+  IcntType icnt_type;
+  uint32_t icnt_freq;
+  uint32_t icnt_latency;
+  SchedulerType scheduler_type;
+  uint32_t precision;
+  Layout layout;
+};
+
 /// A struct representing the application settings.
 struct Settings {
+  /// Complete PIM reads immediately instead of simulating them in DRAM.
   bool fast_read;
+  /// Skip interconnect timing.
   bool fast_icnt;
+  /// Ignore bank conflicts from ACT to a following PIM GACT.
   bool no_conflict_act_to_gact;
+  /// Ignore bank conflicts from PIM GACT to a following ACT.
   bool no_conflict_gact_to_act;
 };
 
+/// The inputs of the config loader, as passed from C++.
+///
+/// Any path may be null or empty to skip that file. `override_file` is an optional TOML file
+/// applied on top of the others, and `sets` points to `num_sets` `key=value` strings applied
+/// last, like `--set` on the command line.
+struct ConfigPaths {
+  const char *config;
+  const char *mem_config;
+  const char *model_config;
+  const char *sys_config;
+  const char *cli_config;
+  const char *override_file;
+  const char *const *sets;
+  size_t num_sets;
+};
+
 
 extern "C" {
 
+/// 增加DRAM通道 `channel` 上的读写计数，同时计入DRAM汇总统计
+///
+/// # 参数
+///
+/// * `channel` - 通道编号
+/// * `op` - `Load`或`Store`
+/// * `n` - 要增加的请求数量
+/// * `cycle` - 当前的DRAM周期
+void add_channel_ops(GlobalCountsCtx *ctx, uint32_t channel, MemOp op, uint64_t n, uint64_t cycle);
+
 /// 增加计算操作的计数
 ///
 /// # 参数
 ///
 /// * `computes` - 要增加的计算操作数量
-void add_computes(GlobalCountsCtx *ctx, uint64_t computes);
+/// * `cycle` - 当前周期，计算单元从空闲变为忙碌时记录
+void add_computes(GlobalCountsCtx *ctx, uint64_t computes, uint64_t cycle);
+
+/// 增加核心 `core` 上的操作计数，同时计入汇总统计
+///
+/// # 参数
+///
+/// * `core` - 核心编号
+/// * `op` - 操作类型，不能是`LoadOrStore`
+/// * `n` - 要增加的操作数量
+/// * `cycle` - 当前的核心周期
+void add_core_ops(GlobalCountsCtx *ctx, uint32_t core, MemOp op, uint64_t n, uint64_t cycle);
+
+/// 在当前阶段增加 `n` 个硬件操作 `op` 的计数
+///
+/// # 参数
+///
+/// * `optype` - 产生这些操作的算子类型，为空时只计入阶段
+void add_hw_ops(GlobalCountsCtx *ctx, HwOp op, uint64_t n, const char *optype);
 
 /// 增加加载操作的计数
 ///
@@ -64,6 +276,9 @@ void add_computes(GlobalCountsCtx *ctx, uint64_t computes);
 /// * `loads` - 要增加的加载操作数量
 void add_loads(GlobalCountsCtx *ctx, uint64_t loads, uint64_t cycle);
 
+/// 增加算子 `optype` 的一个 tile 从分派到完成的周期数
+void add_operation_cycles(GlobalCountsCtx *ctx, const char *optype, uint64_t cycles);
+
 /// 增加存储操作的计数
 ///
 /// # 参数
@@ -71,13 +286,34 @@ void add_loads(GlobalCountsCtx *ctx, uint64_t loads, uint64_t cycle);
 /// * `stores` - 要增加的存储操作数量
 void add_stores(GlobalCountsCtx *ctx, uint64_t stores, uint64_t cycle);
 
+FfiStr config_icnt_config_path(const SimulationConfig *config);
+
+FfiStr config_log_dir(const SimulationConfig *config);
+
+FfiStr config_model_name(const SimulationConfig *config);
+
+FfiStr config_operation_log_output_path(const SimulationConfig *config);
+
+FfiStr config_pim_config_path(const SimulationConfig *config);
+
+FfiStr config_request_dataset_path(const SimulationConfig *config);
+
 void delete_icnt(NoIcnt *ptr);
 
+/// Releases a config returned by [`new_config_with_files`].
+void drop_config(SimulationConfig *config);
+
 /// 释放`GlobalCountsCtx`。
 void drop_global_counts_ctx(GlobalCountsCtx *ctx);
 
+/// Releases a report returned by [`validate_config`].
+void drop_validation_report(ValidationReport *report);
+
 void end_stage(GlobalCountsCtx *ctx, RunStage stage, uint64_t cycle);
 
+/// 获取DRAM通道 `channel` 上当前的读写计数
+uint64_t get_channel_ops(const GlobalCountsCtx *ctx, uint32_t channel, MemOp op);
+
 /// 获取当前的计算操作计数
 ///
 /// # 返回值
@@ -85,6 +321,15 @@ void end_stage(GlobalCountsCtx *ctx, RunStage stage, uint64_t cycle);
 /// 返回当前的计算操作总数
 uint64_t get_computes(const GlobalCountsCtx *ctx);
 
+/// Copies the plain fields of `config`.
+SimulationConfigView get_config_view(const SimulationConfig *config);
+
+/// 获取核心 `core` 上当前的操作计数
+uint64_t get_core_ops(const GlobalCountsCtx *ctx, uint32_t core, MemOp op);
+
+/// Returns the global config installed by `init_config_with_files`.
+const SimulationConfig *get_global_config();
+
 /// 获取当前的加载操作计数
 ///
 /// # 返回值
@@ -106,6 +351,16 @@ uint64_t get_stores(const GlobalCountsCtx *ctx);
 
 size_t get_total_packages(const NoIcnt *self);
 
+/// Loads the config described by `paths` and installs it as the global config.
+///
+/// Same as [`new_config_with_files`], but for C++ callers that still use the single global
+/// config. Returns `false` and logs the reason if loading fails.
+///
+/// # Safety
+///
+/// Every non-null pointer in `paths` must point to a valid nul-terminated string.
+bool init_config_with_files(const ConfigPaths *paths);
+
 /// 初始化日志记录器
 ///
 /// # 参数
@@ -114,37 +369,86 @@ size_t get_total_packages(const NoIcnt *self);
 void init_logger(LogLevel level);
 
 /// Initializes the settings using a default file path ("sjq.toml").
-void init_settings();
+///
+/// Returns `false` and logs the reason if the file cannot be read or parsed.
+bool init_settings();
 
 /// Initializes the settings from a file specified by a C-style string path.
 ///
+/// Returns `false` and logs the reason if the file cannot be read or parsed.
+///
+/// # Safety
+///
+/// `file_path` must be null or point to a valid nul-terminated string.
+bool init_settings_with_file(const char *file_path);
+
+/// Loads the config described by `paths` into a new, independently owned config.
+///
+/// `NEUPIMS_CFG_*` environment variables are applied before the `sets` overrides. Returns null and
+/// logs the reason if loading fails. The config must be released with [`drop_config`].
+///
 /// # Safety
 ///
-/// This function is unsafe because it dereferences a raw pointer.
-void init_settings_with_file(const char *file_path);
+/// Every non-null pointer in `paths` must point to a valid nul-terminated string.
+SimulationConfig *new_config_with_files(const ConfigPaths *paths);
 
-/// 创建一个新的`GlobalCountsCtx`。
-GlobalCountsCtx *new_global_counts_ctx();
+/// 创建一个新的`GlobalCountsCtx`，事件流写入输出目录下的 `events.jsonl`。
+///
+/// 输出目录为 `out_dir`，为空时使用配置的 `log_dir`，都为空时使用当前目录。需要在设置和配置
+/// 初始化之后调用，以使用其中的事件过滤条件、采样间隔和 `log_dir`。
+///
+/// # 返回值
+///
+/// 无法创建输出目录或事件流文件时记录错误并返回空指针
+GlobalCountsCtx *new_global_counts_ctx(const char *out_dir);
 
 NoIcnt *new_icnt();
 
 void npu_finished(GlobalCountsCtx *ctx, uint64_t cycle);
 
+void npu_start(GlobalCountsCtx *ctx, uint64_t cycle);
+
 void pim_finished(GlobalCountsCtx *ctx, uint64_t cycle);
 
+void pim_start(GlobalCountsCtx *ctx, uint64_t cycle);
+
 void push(NoIcnt *self, uint32_t src, uint32_t dest, const void *request);
 
+/// 减少DRAM通道 `channel` 上的读写计数，同时计入DRAM汇总统计
+///
+/// # 返回值
+///
+/// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
+bool reduce_channel_ops(GlobalCountsCtx *ctx,
+                        uint32_t channel,
+                        MemOp op,
+                        uint64_t n,
+                        uint64_t cycle);
+
 /// 减少计算操作的计数
 ///
 /// # 参数
 ///
 /// * `computes` - 要减少的计算操作数量
+/// * `cycle` - 当前周期，计算单元从忙碌变为空闲时记录
 ///
 /// # 返回值
 ///
 /// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
 bool reduce_computes(GlobalCountsCtx *ctx,
-                     uint64_t computes);
+                     uint64_t computes,
+                     uint64_t cycle);
+
+/// 减少核心 `core` 上的操作计数，同时计入汇总统计
+///
+/// # 返回值
+///
+/// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
+bool reduce_core_ops(GlobalCountsCtx *ctx,
+                     uint32_t core,
+                     MemOp op,
+                     uint64_t n,
+                     uint64_t cycle);
 
 /// 减少加载操作的计数
 ///
@@ -172,13 +476,91 @@ bool reduce_stores(GlobalCountsCtx *ctx,
                    uint64_t stores,
                    uint64_t cycle);
 
-/// 保存累计的数据到文件
-void save_global_counts_to_file(const GlobalCountsCtx *ctx);
+/// 登记核心和DRAM通道的数量，使没有任何操作的单元也出现在统计中
+void register_units(GlobalCountsCtx *ctx, uint32_t num_cores, uint32_t num_channels);
+
+/// 根据计数估算每个阶段和每个部件的能耗，连同 [`RunInfo`] 保存到输出目录下的 `energy.json`
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`；配置未初始化时不写入，返回`false`
+bool save_energy_report(GlobalCountsCtx *ctx);
+
+/// 保存累计的数据到输出目录，并将事件流写入磁盘
+///
+/// # 参数
+///
+/// * `dir` - 输出目录，为空时使用 [`GlobalCountsCtx::out_dir`]
+/// * `stem` - 不带扩展名的文件名，为空时使用`counts`
+/// * `format` - 文件格式
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+bool save_global_counts_as(GlobalCountsCtx *ctx,
+                           const char *dir,
+                           const char *stem,
+                           ReportFormat format);
+
+/// 以格式化的 JSON 保存累计的数据到输出目录下的 `counts.json`
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+bool save_global_counts_to_file(GlobalCountsCtx *ctx);
+
+/// 分析 NPU 和 PIM 的重叠情况，连同 [`RunInfo`] 保存到输出目录下的 `overlap.json`
+///
+/// 分析基于未经 `[event_log]` 过滤的完整事件流
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+bool save_overlap_report(const GlobalCountsCtx *ctx);
+
+/// 对每个阶段和每种算子做 roofline 分析，保存到输出目录下的 `roofline.csv` 和 `roofline.svg`
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`；配置未初始化时不写入，返回`false`
+bool save_roofline_report(GlobalCountsCtx *ctx);
+
+/// 保存采样得到的时间序列到输出目录下的 `timeseries.csv`，未开启采样时不写入
+///
+/// # 返回值
+///
+/// 如果写入成功或未开启采样，返回`true`
+bool save_timeseries_to_file(GlobalCountsCtx *ctx);
+
+/// 将事件流以 Chrome Trace 格式保存到输出目录下的 `trace.json`，[`RunInfo`] 记录在 `otherData` 中
+///
+/// 只包含未被 `[event_log]` 过滤的事件，有事件被过滤时会给出警告
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+bool save_trace_to_file(GlobalCountsCtx *ctx);
 
 void update_last_cycle(GlobalCountsCtx *ctx, uint64_t cycle);
 
 void update_stage(GlobalCountsCtx *ctx, RunStage stage, uint64_t cycle);
 
+/// Validates the global config, see [`validate_config_handle`].
+ValidationReport *validate_config();
+
+/// Validates `config` without logging; the caller reports the violations from the report.
+///
+/// The returned report must be released with [`drop_validation_report`].
+ValidationReport *validate_config_handle(const SimulationConfig *config);
+
+/// Returns the number of violations in the report, 0 if the config is valid.
+size_t validation_error_count(const ValidationReport *report);
+
+/// Returns the message of the `index`-th violation, or null if `index` is out of range.
+///
+/// The string is owned by the report and lives as long as it.
+const char *validation_error_message(const ValidationReport *report, size_t index);
+
 }  // extern "C"
 
 }  // namespace sjq_rust
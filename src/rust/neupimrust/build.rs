@@ -1,12 +1,55 @@
-use cbindgen;
 use std::env;
 use std::path::Path;
+use std::process::Command;
+
+/// Returns the commit the crate is built from, with a `-dirty` suffix for uncommitted changes.
+fn git_commit(crate_dir: &str) -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(crate_dir)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let commit = git(&["rev-parse", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty());
+
+    // Rerun when HEAD moves or the index changes, e.g. on checkout, commit or `git add`. The
+    // branch HEAD points to is resolved so that a commit on it is noticed too.
+    let mut watched = vec![
+        "HEAD".to_string(),
+        "index".to_string(),
+        "packed-refs".to_string(),
+    ];
+    watched.extend(git(&["symbolic-ref", "-q", "HEAD"]));
+    for path in watched {
+        if let Some(path) = git(&["rev-parse", "--git-path", &path]) {
+            println!(
+                "cargo:rerun-if-changed={}",
+                Path::new(crate_dir).join(path).display()
+            );
+        }
+    }
+    Some(if dirty { commit + "-dirty" } else { commit })
+}
 
 fn main() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let cbindgen_toml = Path::new(&crate_dir).join("cbindgen.toml");
 
-    // println!("cargo:rerun-if-changed={}", cbindgen_toml.display());
+    // Listing the git files replaces cargo's default of rerunning on any change in the package,
+    // so the inputs of the bindings are listed as well.
+    println!("cargo:rerun-if-changed={}", cbindgen_toml.display());
+    println!(
+        "cargo:rerun-if-changed={}",
+        Path::new(&crate_dir).join("src").display()
+    );
+
+    if let Some(commit) = git_commit(&crate_dir) {
+        println!("cargo:rustc-env=NEUPIMS_GIT_COMMIT={commit}");
+    }
 
     cbindgen::Builder::new()
         .with_config(
@@ -0,0 +1,292 @@
+//! Busy/idle tracking of simulated resources.
+//!
+//! A resource is busy while it has at least one ongoing operation. [`ActivityTracker`] counts the
+//! operations of every registered resource and, on each idle/busy transition, records the length
+//! of the interval that ended. A union group is a resource that is busy while any of its members
+//! is, e.g. "any memory traffic" over loads and stores; groups may contain other groups.
+//! Group memberships are not serialized, so a deserialized tracker only holds the statistics.
+//!
+//! The resources of a tracker may be driven by different clocks, e.g. the aggregate over all
+//! cores receives the cycle of whichever core reports, and cores advance one after the other. A
+//! cycle earlier than the start of the current period is therefore taken as that start, so that
+//! periods never have a negative length and the status start never moves back.
+
+use std::collections::BTreeMap;
+
+use derive_more::derive::AddAssign;
+use serde::{Deserialize, Serialize};
+
+use crate::histogram::{Histogram, HistogramStrategy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemStatus {
+    /// Idle since the given cycle.
+    Idle(u64),
+    /// Busy since the given cycle.
+    Busy(u64),
+}
+
+impl Default for MemStatus {
+    fn default() -> Self {
+        MemStatus::Idle(0)
+    }
+}
+
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, AddAssign,
+)]
+pub struct Cycle(pub u64);
+
+/// A change of a resource's status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    BusyStart,
+    BusyEnd,
+}
+
+/// The statistics of one resource.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceActivity {
+    /// Ongoing operations. For a group, the number of busy members.
+    pub current: u64,
+    /// Operations started so far. For a group, the number of member busy periods.
+    pub total: u64,
+    pub status: MemStatus,
+    pub busy_cycles: Cycle,
+    pub idle_cycles: Cycle,
+    /// How many busy periods lasted each number of cycles.
+    pub busy_histo: Histogram,
+    /// How many idle periods lasted each number of cycles.
+    pub idle_histo: Histogram,
+}
+
+impl ResourceActivity {
+    pub fn new(histogram: HistogramStrategy) -> Self {
+        ResourceActivity {
+            current: 0,
+            total: 0,
+            status: MemStatus::default(),
+            busy_cycles: Cycle::default(),
+            idle_cycles: Cycle::default(),
+            busy_histo: Histogram::new(histogram),
+            idle_histo: Histogram::new(histogram),
+        }
+    }
+
+    /// The share of the finished busy and idle periods that the resource was busy, `None` before
+    /// any period finished.
+    pub fn busy_ratio(&self) -> Option<f64> {
+        let cycles = self.busy_cycles.0 + self.idle_cycles.0;
+        (cycles > 0).then(|| self.busy_cycles.0 as f64 / cycles as f64)
+    }
+}
+
+/// Tracks the activity of resources of type `R` and of union groups over them.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent, bound(deserialize = "R: Ord + Deserialize<'de>"))]
+pub struct ActivityTracker<R: Ord> {
+    resources: BTreeMap<R, ResourceActivity>,
+    /// The groups each resource is a member of.
+    #[serde(skip)]
+    groups_of: BTreeMap<R, Vec<R>>,
+    #[serde(skip)]
+    histogram: HistogramStrategy,
+}
+
+impl<R: Ord> Default for ActivityTracker<R> {
+    fn default() -> Self {
+        Self::with_histogram(HistogramStrategy::default())
+    }
+}
+
+impl<R: Ord> ActivityTracker<R> {
+    /// A tracker recording the busy and idle periods of its resources with `histogram`.
+    pub fn with_histogram(histogram: HistogramStrategy) -> Self {
+        Self {
+            resources: BTreeMap::new(),
+            groups_of: BTreeMap::new(),
+            histogram,
+        }
+    }
+}
+
+impl<R: Ord + Copy> ActivityTracker<R> {
+    /// Registers a resource so that it appears in the statistics even if it is never used.
+    /// Resources are also registered by their first operation.
+    pub fn register(&mut self, resource: R) {
+        self.activity_mut(resource);
+    }
+
+    fn activity_mut(&mut self, resource: R) -> &mut ResourceActivity {
+        let histogram = self.histogram;
+        self.resources
+            .entry(resource)
+            .or_insert_with(|| ResourceActivity::new(histogram))
+    }
+
+    /// Registers `group` as busy whenever any of `members` is busy.
+    pub fn register_group(&mut self, group: R, members: &[R]) {
+        self.register(group);
+        for &member in members {
+            self.register(member);
+            self.groups_of.entry(member).or_default().push(group);
+        }
+    }
+
+    pub fn get(&self, resource: R) -> Option<&ResourceActivity> {
+        self.resources.get(&resource)
+    }
+
+    /// The number of ongoing operations of `resource`.
+    pub fn current(&self, resource: R) -> u64 {
+        self.get(resource).map_or(0, |activity| activity.current)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (R, &ResourceActivity)> {
+        self.resources
+            .iter()
+            .map(|(&resource, activity)| (resource, activity))
+    }
+
+    /// Starts `n` operations on `resource` at `cycle`. `on_transition` is called for the
+    /// resource and then for each group that becomes busy because of it.
+    pub fn add(
+        &mut self,
+        resource: R,
+        n: u64,
+        cycle: u64,
+        on_transition: &mut impl FnMut(R, Transition),
+    ) {
+        let activity = self.activity_mut(resource);
+        activity.current += n;
+        activity.total += n;
+        if n == 0 {
+            return;
+        }
+        if let MemStatus::Idle(start_cycle) = activity.status {
+            let cycle = cycle.max(start_cycle);
+            let idle_duration = cycle - start_cycle;
+            if idle_duration != 0 {
+                activity.idle_histo.record(idle_duration);
+                activity.idle_cycles += Cycle(idle_duration);
+            }
+            activity.status = MemStatus::Busy(cycle);
+            on_transition(resource, Transition::BusyStart);
+            for group in self.groups_of.get(&resource).cloned().unwrap_or_default() {
+                self.add(group, 1, cycle, on_transition);
+            }
+        }
+    }
+
+    /// Finishes `n` operations on `resource` at `cycle`. `on_transition` is called for the
+    /// resource and then for each group that becomes idle because of it.
+    ///
+    /// Returns `false`, changing nothing, if fewer than `n` operations are ongoing.
+    pub fn reduce(
+        &mut self,
+        resource: R,
+        n: u64,
+        cycle: u64,
+        on_transition: &mut impl FnMut(R, Transition),
+    ) -> bool {
+        let Some(activity) = self.resources.get_mut(&resource) else {
+            return n == 0;
+        };
+        if activity.current < n {
+            return false;
+        }
+        activity.current -= n;
+        if activity.current == 0 {
+            if let MemStatus::Busy(start_cycle) = activity.status {
+                let cycle = cycle.max(start_cycle);
+                let busy_duration = cycle - start_cycle;
+                activity.busy_histo.record(busy_duration);
+                activity.busy_cycles += Cycle(busy_duration);
+                activity.status = MemStatus::Idle(cycle);
+                on_transition(resource, Transition::BusyEnd);
+                for group in self.groups_of.get(&resource).cloned().unwrap_or_default() {
+                    self.reduce(group, 1, cycle, on_transition);
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    enum Unit {
+        A,
+        B,
+        AOrB,
+        Any,
+    }
+
+    fn tracker() -> ActivityTracker<Unit> {
+        let mut tracker = ActivityTracker::default();
+        tracker.register_group(Unit::AOrB, &[Unit::A, Unit::B]);
+        tracker.register_group(Unit::Any, &[Unit::AOrB]);
+        tracker
+    }
+
+    #[test]
+    fn test_union_group() {
+        let mut tracker = tracker();
+        let mut transitions = vec![];
+        let mut record = |unit, transition| transitions.push((unit, transition));
+        // A: 10..20, B: 15..30, so A or B is busy over 10..30.
+        tracker.add(Unit::A, 2, 10, &mut record);
+        tracker.add(Unit::B, 1, 15, &mut record);
+        assert!(tracker.reduce(Unit::A, 2, 20, &mut record));
+        assert!(tracker.reduce(Unit::B, 1, 30, &mut record));
+
+        let group = tracker.get(Unit::AOrB).unwrap();
+        assert_eq!(group.busy_cycles, Cycle(20));
+        assert_eq!(group.idle_cycles, Cycle(10));
+        assert_eq!(group.status, MemStatus::Idle(30));
+        assert_eq!(tracker.get(Unit::Any).unwrap().busy_cycles, Cycle(20));
+        assert_eq!(tracker.get(Unit::A).unwrap().total, 2);
+        assert_eq!(
+            transitions,
+            vec![
+                (Unit::A, Transition::BusyStart),
+                (Unit::AOrB, Transition::BusyStart),
+                (Unit::Any, Transition::BusyStart),
+                (Unit::B, Transition::BusyStart),
+                (Unit::A, Transition::BusyEnd),
+                (Unit::B, Transition::BusyEnd),
+                (Unit::AOrB, Transition::BusyEnd),
+                (Unit::Any, Transition::BusyEnd),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_below_zero() {
+        let mut tracker = tracker();
+        let mut ignore = |_, _| {};
+        assert!(!tracker.reduce(Unit::A, 1, 5, &mut ignore));
+        tracker.add(Unit::A, 1, 5, &mut ignore);
+        assert!(!tracker.reduce(Unit::A, 2, 6, &mut ignore));
+        assert_eq!(tracker.current(Unit::A), 1);
+        assert_eq!(tracker.get(Unit::A).unwrap().status, MemStatus::Busy(5));
+    }
+
+    #[test]
+    fn test_cycle_before_period_start() {
+        let mut tracker = tracker();
+        let mut ignore = |_, _| {};
+        // B reports from a clock one cycle behind A's
+        tracker.add(Unit::A, 1, 10, &mut ignore);
+        assert!(tracker.reduce(Unit::A, 1, 12, &mut ignore));
+        tracker.add(Unit::B, 1, 11, &mut ignore);
+        assert!(tracker.reduce(Unit::B, 1, 11, &mut ignore));
+        let group = tracker.get(Unit::AOrB).unwrap();
+        assert_eq!(group.busy_cycles, Cycle(2));
+        assert_eq!(group.idle_cycles, Cycle(10));
+        assert_eq!(group.status, MemStatus::Idle(12));
+    }
+}
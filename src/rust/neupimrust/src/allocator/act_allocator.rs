@@ -1,6 +1,9 @@
-use crate::global_config::get_config;
+use std::sync::Arc;
+
+use crate::global_config::SimulationConfig;
 
 pub struct ActivationAllocator {
+    pub config: Arc<SimulationConfig>,
     pub base_addr: usize,
     pub top_addr: usize,
     pub buf_size: usize,
@@ -8,24 +11,26 @@ pub struct ActivationAllocator {
 }
 
 impl ActivationAllocator {
-    pub fn new(base_addr: usize) -> Self {
+    pub fn new(config: Arc<SimulationConfig>, base_addr: usize) -> Self {
+        let buf_size = config.hbm_act_buf_size as usize;
         ActivationAllocator {
+            config,
             base_addr,
             top_addr: base_addr,
-            buf_size: get_config().hbm_act_buf_size as usize,
-            buf_limit: base_addr + get_config().hbm_act_buf_size as usize,
+            buf_size,
+            buf_limit: base_addr + buf_size,
         }
     }
     pub fn allocate(&mut self, size: usize) -> usize {
         assert!(self.top_addr + size < self.buf_limit);
-        let alignment = get_config().dram_req_size as usize;
+        let alignment = self.config.dram_req_size as usize;
 
         let result = self.top_addr;
         self.top_addr += size;
         if self.top_addr & (alignment - 1) != 0 {
             self.top_addr += alignment - (self.top_addr & (alignment - 1));
         }
-        return result;
+        result
     }
     pub fn reset(&mut self) {
         self.top_addr = self.base_addr;
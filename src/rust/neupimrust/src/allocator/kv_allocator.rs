@@ -1,23 +1,27 @@
-use crate::global_config::get_config;
+use std::sync::Arc;
+
+use crate::global_config::SimulationConfig;
 
 pub struct KVCacheAllocator {
+    pub config: Arc<SimulationConfig>,
     pub base_addr: usize,
     pub top_addr: usize,
 }
 
 impl KVCacheAllocator {
-    pub fn new(base_addr: usize, top_addr: usize) -> Self {
+    pub fn new(config: Arc<SimulationConfig>, base_addr: usize, top_addr: usize) -> Self {
         KVCacheAllocator {
+            config,
             base_addr,
             top_addr,
         }
     }
     pub fn allocate(&mut self, size: usize) -> usize {
-        let unit = get_config().dram_req_size;
+        let unit = self.config.dram_req_size;
         let unit = unit as usize;
         let result = self.top_addr;
-        self.top_addr += (size + unit - 1) / unit;
-        return result;
+        self.top_addr += size.div_ceil(unit);
+        result
     }
     pub fn reset(&mut self) {
         self.top_addr = self.base_addr;
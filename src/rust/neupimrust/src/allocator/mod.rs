@@ -1,10 +1,38 @@
-use crate::global_config::get_config;
+use crate::global_config::SimulationConfig;
 
 pub mod act_allocator;
 pub mod kv_allocator;
 pub mod weight_allocator;
-pub fn get_aligned_addr(addr: usize) -> usize {
-    let config = get_config();
+pub fn get_aligned_addr(config: &SimulationConfig, addr: usize) -> usize {
     let unit = config.dram_req_size;
     addr - (addr & (unit as usize - 1))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{act_allocator::ActivationAllocator, *};
+
+    #[test]
+    fn test_two_configs_in_one_process() {
+        let small = Arc::new(SimulationConfig {
+            dram_req_size: 32,
+            hbm_act_buf_size: 1 << 20,
+            ..Default::default()
+        });
+        let large = Arc::new(SimulationConfig {
+            dram_req_size: 64,
+            ..(*small).clone()
+        });
+        assert_eq!(get_aligned_addr(&small, 100), 96);
+        assert_eq!(get_aligned_addr(&large, 100), 64);
+
+        let mut small_alloc = ActivationAllocator::new(small, 0);
+        let mut large_alloc = ActivationAllocator::new(large, 0);
+        small_alloc.allocate(1);
+        large_alloc.allocate(1);
+        assert_eq!(small_alloc.top_addr, 32);
+        assert_eq!(large_alloc.top_addr, 64);
+    }
+}
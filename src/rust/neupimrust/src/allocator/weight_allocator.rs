@@ -1,31 +1,33 @@
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use crate::global_config::get_config;
+use crate::global_config::SimulationConfig;
 pub static ALLOCATOR: OnceLock<Mutex<WeightAllocator>> = OnceLock::new();
 pub struct WeightAllocator {
+    pub config: Arc<SimulationConfig>,
     pub base_addr: usize,
     pub top_addr: usize,
 }
 
 impl WeightAllocator {
-    pub fn new(base_addr: usize, top_addr: usize) -> Self {
+    pub fn new(config: Arc<SimulationConfig>, base_addr: usize, top_addr: usize) -> Self {
         WeightAllocator {
+            config,
             base_addr,
             top_addr,
         }
     }
     pub fn allocate(&mut self, size: usize) -> usize {
-        let unit = get_config().dram_req_size * get_config().dram_channels;
+        let unit = self.config.dram_req_size * self.config.dram_channels;
         let unit = unit as usize;
         let result = self.top_addr;
-        self.top_addr += (size + unit - 1) / unit;
-        return result;
+        self.top_addr += size.div_ceil(unit);
+        result
     }
     pub fn reset(&mut self) {
         self.top_addr = self.base_addr;
     }
 
     pub fn get_next_addr(&self) -> usize {
-        super::get_aligned_addr(self.top_addr) + get_config().dram_req_size as usize
+        super::get_aligned_addr(&self.config, self.top_addr) + self.config.dram_req_size as usize
     }
 }
@@ -0,0 +1,923 @@
+//! Layered loading of [`SimulationConfig`] from the split config files under `configs/`.
+//!
+//! The files are merged in a fixed order: hardware, memory, model, system, client and finally any
+//! TOML override files. On top of the files come `NEUPIMS_CFG_*` environment variables and then
+//! `key=value` overrides from the command line. A later layer wins over an earlier one, and every
+//! field remembers where it was last set so a surprising value can be traced back to its origin.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{c_char, CStr},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_json::{Map, Value};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    global_config::{set_config, SimulationConfig},
+    hf_import::{import_hf_config, is_hf_config},
+    model_zoo::ModelPreset,
+};
+
+/// Keys used by the legacy JSON files that differ from the field names of [`SimulationConfig`].
+///
+/// Each entry is `(legacy key, field name, multiplier)`; the multiplier converts the legacy unit
+/// into the unit expected by the field. These are the only spellings accepted besides the field
+/// names themselves, any other key is rejected.
+pub(crate) const LEGACY_KEYS: &[(&str, &str, u64)] = &[
+    ("HBM_size", "hbm_size", 1 << 30),
+    ("HBM_act_buf_size", "hbm_act_buf_size", 1 << 20),
+    ("scheduler", "scheduler_type", 1),
+];
+
+/// Keys of older config files that the simulator never read. They are skipped with a warning
+/// rather than rejected, so that those files keep loading.
+pub(crate) const UNUSED_KEYS: &[&str] = &["systolic_array_count"];
+
+/// Prefix of the keys of a config file that are comments, e.g. `"__sram_size"` keeping an old
+/// value around. They are skipped without a warning.
+pub const COMMENT_PREFIX: &str = "__";
+
+/// Prefix of environment variables overriding config fields.
+///
+/// The rest of the name is the upper-cased key with `.` spelled as `__`, so
+/// `NEUPIMS_CFG_MAX_BATCH_SIZE=256` is the same as `--set max_batch_size=256`. Other `NEUPIMS_*`
+/// variables, such as `NEUPIMS_HOME`, are left alone.
+pub const ENV_PREFIX: &str = "NEUPIMS_CFG_";
+
+/// The config file a value was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLayer {
+    Hardware,
+    Memory,
+    Model,
+    System,
+    Client,
+    Override,
+}
+
+impl fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigLayer::Hardware => "hardware config",
+            ConfigLayer::Memory => "memory config",
+            ConfigLayer::Model => "model config",
+            ConfigLayer::System => "system config",
+            ConfigLayer::Client => "client config",
+            ConfigLayer::Override => "override",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Where the value of a single field came from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ConfigSource {
+    /// The field was not set by any file and keeps its default value.
+    #[default]
+    Default,
+    File {
+        layer: ConfigLayer,
+        path: PathBuf,
+    },
+    /// The field was set by an axis of a parameter sweep.
+    Sweep,
+    /// The field was set by the named environment variable.
+    Env(String),
+    /// The field was set by a `--set` override.
+    CommandLine,
+    /// The field was not set and was taken from the named model preset.
+    Preset(&'static str),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => f.write_str("default"),
+            ConfigSource::File { layer, path } => write!(f, "{layer} {}", path.display()),
+            ConfigSource::Sweep => f.write_str("sweep axis"),
+            ConfigSource::Env(name) => write!(f, "environment variable {name}"),
+            ConfigSource::CommandLine => f.write_str("command line"),
+            ConfigSource::Preset(name) => write!(f, "model preset {name}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    Toml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// The file parsed, but its top level is not a table of fields.
+    NotATable { path: PathBuf },
+    /// A file or an override sets a field that does not exist.
+    UnknownField { field: String, source: ConfigSource },
+    /// An override is not of the form `key=value`.
+    InvalidOverride(String),
+    /// A field has a value that does not fit its type.
+    Field {
+        field: String,
+        source: ConfigSource,
+        message: String,
+    },
+    /// The merged table could not be deserialized for a reason not tied to a single field.
+    Deserialize(serde_json::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            ConfigError::Json { path, source } => {
+                write!(f, "failed to parse {} as JSON: {source}", path.display())
+            }
+            ConfigError::Toml { path, source } => {
+                write!(f, "failed to parse {} as TOML: {source}", path.display())
+            }
+            ConfigError::NotATable { path } => {
+                write!(f, "{} does not contain a table of fields", path.display())
+            }
+            ConfigError::UnknownField { field, source } => {
+                write!(f, "unknown config field `{field}` (from {source})")
+            }
+            ConfigError::InvalidOverride(raw) => {
+                write!(f, "invalid override `{raw}`, expected `key=value`")
+            }
+            ConfigError::Field {
+                field,
+                source,
+                message,
+            } => write!(f, "invalid value for `{field}` (from {source}): {message}"),
+            ConfigError::Deserialize(source) => write!(f, "invalid config: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Json { source, .. } => Some(source),
+            ConfigError::Toml { source, .. } => Some(source),
+            ConfigError::Deserialize(source) => Some(source),
+            ConfigError::NotATable { .. }
+            | ConfigError::UnknownField { .. }
+            | ConfigError::InvalidOverride(_)
+            | ConfigError::Field { .. } => None,
+        }
+    }
+}
+
+/// The set of files describing one experiment. Missing files are simply skipped.
+#[derive(Debug, Clone, Default, serde::Deserialize, schemars::JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFiles {
+    /// Hardware (core, vector unit, SRAM, interconnect) config.
+    pub config: Option<PathBuf>,
+    /// Memory (DRAM and PIM) config.
+    pub mem_config: Option<PathBuf>,
+    /// Model config, or a HuggingFace `config.json`.
+    pub model_config: Option<PathBuf>,
+    /// System (run mode, batching) config.
+    pub sys_config: Option<PathBuf>,
+    /// Client config. A `.json` file is merged like the others; anything else (usually a request
+    /// trace `.csv`) is taken as `request_dataset_path`.
+    pub cli_config: Option<PathBuf>,
+    /// TOML files applied on top of everything else, in order.
+    pub overrides: Vec<PathBuf>,
+}
+
+/// A single `key=value` override of a config field.
+///
+/// The key may be dotted to reach into nested tables. The value is parsed as a TOML value, so
+/// `true`, `128` and `"text"` keep their types; anything that is not valid TOML is taken as a
+/// plain string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOverride {
+    pub key: String,
+    pub value: Value,
+}
+
+impl ConfigOverride {
+    fn parse(key: &str, raw: &str) -> Self {
+        let value = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .map(|value| serde_json::to_value(value).expect("TOML values are valid JSON"))
+            .unwrap_or_else(|| Value::from(raw));
+        ConfigOverride {
+            key: key.trim().to_string(),
+            value,
+        }
+    }
+
+    /// Builds an override from an environment variable, if its name starts with [`ENV_PREFIX`].
+    pub fn from_env_var(name: &str, value: &str) -> Option<Self> {
+        let key = name.strip_prefix(ENV_PREFIX)?;
+        Some(Self::parse(&key.to_lowercase().replace("__", "."), value))
+    }
+
+    /// The environment variable setting the same key.
+    pub fn env_var_name(&self) -> String {
+        format!("{ENV_PREFIX}{}", self.key.replace('.', "__").to_uppercase())
+    }
+}
+
+impl FromStr for ConfigOverride {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, raw)) if !key.trim().is_empty() => Ok(Self::parse(key, raw.trim())),
+            _ => Err(ConfigError::InvalidOverride(s.to_string())),
+        }
+    }
+}
+
+/// A merged config together with the origin of every field.
+#[derive(Debug, Clone)]
+pub struct LoadedConfig {
+    pub config: SimulationConfig,
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+impl LoadedConfig {
+    /// Returns where `field` was set, or [`ConfigSource::Default`] if no file set it.
+    pub fn source_of(&self, field: &str) -> &ConfigSource {
+        static DEFAULT: ConfigSource = ConfigSource::Default;
+        self.sources.get(field).unwrap_or(&DEFAULT)
+    }
+}
+
+/// Accumulates config layers into one table before deserializing it.
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    table: Map<String, Value>,
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a builder with all files in `files` merged.
+    pub fn from_files(files: &ConfigFiles) -> Result<Self, ConfigError> {
+        let mut builder = ConfigBuilder::new();
+        let layers = [
+            (ConfigLayer::Hardware, &files.config),
+            (ConfigLayer::Memory, &files.mem_config),
+            (ConfigLayer::Model, &files.model_config),
+            (ConfigLayer::System, &files.sys_config),
+        ];
+        for (layer, path) in layers {
+            if let Some(path) = path {
+                builder.merge_file(layer, path)?;
+            }
+        }
+        if let Some(path) = &files.cli_config {
+            if path.extension().is_some_and(|ext| ext == "json") {
+                builder.merge_file(ConfigLayer::Client, path)?;
+            } else {
+                builder.set(
+                    "request_dataset_path".to_string(),
+                    Value::from(path.to_string_lossy().into_owned()),
+                    ConfigSource::File {
+                        layer: ConfigLayer::Client,
+                        path: path.clone(),
+                    },
+                );
+            }
+        }
+        for path in &files.overrides {
+            builder.merge_file(ConfigLayer::Override, path)?;
+        }
+        Ok(builder)
+    }
+
+    /// Reads `path` and merges it as `layer`. `.toml` files are parsed as TOML, everything else
+    /// as JSON.
+    ///
+    /// A result file carrying a `run_info` section (e.g. `counts.json`) is accepted as well, and
+    /// contributes the config recorded in it, so a run can be repeated from its output. A model
+    /// config may also be a HuggingFace `config.json`, see [`crate::hf_import`].
+    pub fn merge_file(&mut self, layer: ConfigLayer, path: &Path) -> Result<(), ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let value = if path.extension().is_some_and(|ext| ext == "toml") {
+            let table: toml::Table =
+                toml::from_str(&content).map_err(|source| ConfigError::Toml {
+                    path: path.to_owned(),
+                    source,
+                })?;
+            serde_json::to_value(table).expect("a TOML table is always representable as JSON")
+        } else {
+            serde_json::from_str(&content).map_err(|source| ConfigError::Json {
+                path: path.to_owned(),
+                source,
+            })?
+        };
+        match value {
+            Value::Object(mut table) => {
+                if let Some(Value::Object(recorded)) = table
+                    .get_mut("run_info")
+                    .and_then(|run_info| run_info.get_mut("config"))
+                {
+                    table = std::mem::take(recorded);
+                }
+                if layer == ConfigLayer::Model && is_hf_config(&table) {
+                    table = import_hf_config(path, table)?.fields;
+                }
+                self.merge_table(layer, path, table)
+            }
+            _ => Err(ConfigError::NotATable {
+                path: path.to_owned(),
+            }),
+        }
+    }
+
+    /// Merges an already parsed table as `layer`, translating legacy keys on the way. Comment
+    /// keys and [`UNUSED_KEYS`] are skipped.
+    pub fn merge_table(
+        &mut self,
+        layer: ConfigLayer,
+        path: &Path,
+        table: Map<String, Value>,
+    ) -> Result<(), ConfigError> {
+        for (key, value) in table {
+            let source = ConfigSource::File {
+                layer,
+                path: path.to_owned(),
+            };
+            if key.starts_with(COMMENT_PREFIX) {
+                continue;
+            }
+            if UNUSED_KEYS.contains(&key.as_str()) {
+                warn!("`{key}` (from {source}) is not used by the simulator, ignoring it");
+                continue;
+            }
+            let (key, value) = normalize_legacy_key(key, value, &source)?;
+            self.set(key, value, source);
+        }
+        Ok(())
+    }
+
+    /// Applies one override. Legacy keys are translated like in files, and unknown fields are
+    /// rejected, so that a typo does not go unnoticed.
+    pub fn apply_override(
+        &mut self,
+        over: &ConfigOverride,
+        source: ConfigSource,
+    ) -> Result<(), ConfigError> {
+        let (key, over_value) =
+            normalize_legacy_key(over.key.clone(), over.value.clone(), &source)?;
+        let mut path = key.split('.');
+        let field = path.next().expect("split yields at least one item");
+        if !SimulationConfig::field_names().iter().any(|f| f == field) {
+            return Err(ConfigError::UnknownField {
+                field: field.to_string(),
+                source,
+            });
+        }
+        let nested: Vec<&str> = path.collect();
+        let value = match nested.split_last() {
+            None => over_value,
+            Some((last, parents)) => {
+                let mut value = self.table.get(field).cloned().unwrap_or_default();
+                let mut slot = &mut value;
+                for key in parents.iter().chain([last]) {
+                    if !slot.is_object() {
+                        *slot = Value::Object(Map::new());
+                    }
+                    slot = slot
+                        .as_object_mut()
+                        .expect("just made an object")
+                        .entry(key.to_string())
+                        .or_insert(Value::Null);
+                }
+                *slot = over_value;
+                value
+            }
+        };
+        self.set(field.to_string(), value, source);
+        Ok(())
+    }
+
+    /// Applies every `NEUPIMS_CFG_*` variable in `vars`, in name order.
+    pub fn apply_env_vars(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        let vars: BTreeMap<_, _> = vars.into_iter().collect();
+        for (name, value) in vars {
+            if let Some(over) = ConfigOverride::from_env_var(&name, &value) {
+                self.apply_override(&over, ConfigSource::Env(name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the `NEUPIMS_CFG_*` variables of the current process.
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_env_vars(std::env::vars())
+    }
+
+    /// Applies `--set` overrides, in order.
+    pub fn apply_command_line(&mut self, overrides: &[ConfigOverride]) -> Result<(), ConfigError> {
+        for over in overrides {
+            self.apply_override(over, ConfigSource::CommandLine)?;
+        }
+        Ok(())
+    }
+
+    /// Sets a single field, replacing any earlier value.
+    pub fn set(&mut self, key: String, value: Value, source: ConfigSource) {
+        self.sources.insert(key.clone(), source);
+        self.table.insert(key, value);
+    }
+
+    /// Deserializes the merged table. If `model_name` names a [`ModelPreset`], the preset fills
+    /// in every model field that was not set.
+    pub fn build(self) -> Result<LoadedConfig, ConfigError> {
+        let ConfigBuilder {
+            mut table,
+            mut sources,
+        } = self;
+        if let Some(preset) = table
+            .get("model_name")
+            .and_then(Value::as_str)
+            .and_then(ModelPreset::find)
+        {
+            for (field, value) in preset.fields() {
+                if !table.contains_key(&field) {
+                    sources.insert(field.clone(), ConfigSource::Preset(preset.name));
+                    table.insert(field, value);
+                }
+            }
+        }
+        if let Some(field) = table
+            .keys()
+            .find(|key| !SimulationConfig::field_names().contains(key))
+        {
+            return Err(ConfigError::UnknownField {
+                source: sources.get(field).cloned().unwrap_or_default(),
+                field: field.clone(),
+            });
+        }
+        match serde_json::from_value(Value::Object(table.clone())) {
+            Ok(config) => Ok(LoadedConfig { config, sources }),
+            Err(err) => {
+                Err(locate_field_error(&table, &sources).unwrap_or(ConfigError::Deserialize(err)))
+            }
+        }
+    }
+}
+
+/// Finds the first field whose value alone fails to deserialize.
+///
+/// Each value is tried on top of [`SimulationConfig::default_fields`], which is a valid config,
+/// so the attempt only fails if that value is wrong. If no value is wrong, the table is missing
+/// a required field and the caller reports serde's error.
+fn locate_field_error(
+    table: &Map<String, Value>,
+    sources: &BTreeMap<String, ConfigSource>,
+) -> Option<ConfigError> {
+    table.iter().find_map(|(key, value)| {
+        let mut single = SimulationConfig::default_fields();
+        single.insert(key.clone(), value.clone());
+        serde_json::from_value::<SimulationConfig>(Value::Object(single))
+            .err()
+            .map(|err| ConfigError::Field {
+                field: key.clone(),
+                source: sources.get(key).cloned().unwrap_or_default(),
+                message: err.to_string(),
+            })
+    })
+}
+
+/// Translates a legacy key into its field, scaling a plain number into the field's unit.
+fn normalize_legacy_key(
+    key: String,
+    value: Value,
+    source: &ConfigSource,
+) -> Result<(String, Value), ConfigError> {
+    let Some((_, field, multiplier)) = LEGACY_KEYS.iter().find(|(legacy, _, _)| *legacy == key)
+    else {
+        return Ok((key, value));
+    };
+    let value = match value.as_u64() {
+        Some(v) => Value::from(
+            v.checked_mul(*multiplier)
+                .ok_or_else(|| ConfigError::Field {
+                    field: key.clone(),
+                    source: source.clone(),
+                    message: format!("{v} * {multiplier} is out of range"),
+                })?,
+        ),
+        None => value,
+    };
+    Ok((field.to_string(), value))
+}
+
+/// Loads and merges all files in `files`, without environment or command line overrides.
+pub fn load_config(files: &ConfigFiles) -> Result<LoadedConfig, ConfigError> {
+    ConfigBuilder::from_files(files)?.build()
+}
+
+/// Loads `files`, then applies the `NEUPIMS_CFG_*` environment variables and finally `overrides`.
+pub fn load_config_with_overrides(
+    files: &ConfigFiles,
+    overrides: &[ConfigOverride],
+) -> Result<LoadedConfig, ConfigError> {
+    let mut builder = ConfigBuilder::from_files(files)?;
+    builder.apply_env()?;
+    builder.apply_command_line(overrides)?;
+    builder.build()
+}
+
+/// Converts a nullable C string into a path. Null and empty strings both mean "no file".
+pub(crate) fn c_path(path: *const c_char) -> Option<PathBuf> {
+    if path.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(path) };
+    let path = path.to_string_lossy();
+    (!path.is_empty()).then(|| PathBuf::from(path.into_owned()))
+}
+
+/// The inputs of the config loader, as passed from C++.
+///
+/// Any path may be null or empty to skip that file. `override_file` is an optional TOML file
+/// applied on top of the others, and `sets` points to `num_sets` `key=value` strings applied
+/// last, like `--set` on the command line.
+#[repr(C)]
+pub struct ConfigPaths {
+    pub config: *const c_char,
+    pub mem_config: *const c_char,
+    pub model_config: *const c_char,
+    pub sys_config: *const c_char,
+    pub cli_config: *const c_char,
+    pub override_file: *const c_char,
+    pub sets: *const *const c_char,
+    pub num_sets: usize,
+}
+
+impl ConfigPaths {
+    fn load(&self) -> Result<LoadedConfig, ConfigError> {
+        let files = ConfigFiles {
+            config: c_path(self.config),
+            mem_config: c_path(self.mem_config),
+            model_config: c_path(self.model_config),
+            sys_config: c_path(self.sys_config),
+            cli_config: c_path(self.cli_config),
+            overrides: c_path(self.override_file).into_iter().collect(),
+        };
+        let sets = if self.sets.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.sets, self.num_sets) }
+        };
+        let overrides = sets
+            .iter()
+            .map(|set| unsafe { CStr::from_ptr(*set) }.to_string_lossy().parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        load_config_with_overrides(&files, &overrides)
+    }
+}
+
+/// Loads the config described by `paths`, logging where each field came from.
+fn load_config_from_c_paths(paths: &ConfigPaths) -> Option<SimulationConfig> {
+    match paths.load() {
+        Ok(loaded) => {
+            for (field, source) in &loaded.sources {
+                debug!("config {field} <- {source}");
+            }
+            info!("config loaded: {}", loaded.config.model_name);
+            Some(loaded.config)
+        }
+        Err(err) => {
+            error!("failed to load config: {err}");
+            None
+        }
+    }
+}
+
+/// Loads the config described by `paths` into a new, independently owned config.
+///
+/// `NEUPIMS_CFG_*` environment variables are applied before the `sets` overrides. Returns null and
+/// logs the reason if loading fails. The config must be released with [`drop_config`].
+///
+/// # Safety
+///
+/// Every non-null pointer in `paths` must point to a valid nul-terminated string.
+#[no_mangle]
+pub extern "C" fn new_config_with_files(paths: &ConfigPaths) -> *mut SimulationConfig {
+    match load_config_from_c_paths(paths) {
+        Some(config) => Box::into_raw(Box::new(config)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Releases a config returned by [`new_config_with_files`].
+#[no_mangle]
+pub extern "C" fn drop_config(config: *mut SimulationConfig) {
+    if config.is_null() {
+        return;
+    }
+    unsafe {
+        drop(Box::from_raw(config));
+    }
+}
+
+/// Loads the config described by `paths` and installs it as the global config.
+///
+/// Same as [`new_config_with_files`], but for C++ callers that still use the single global
+/// config. Returns `false` and logs the reason if loading fails.
+///
+/// # Safety
+///
+/// Every non-null pointer in `paths` must point to a valid nul-terminated string.
+#[no_mangle]
+pub extern "C" fn init_config_with_files(paths: &ConfigPaths) -> bool {
+    load_config_from_c_paths(paths).is_some_and(set_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_config::{DramType, Layout, RunMode, SchedulerType};
+
+    fn configs_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../configs")
+    }
+
+    fn repo_files() -> ConfigFiles {
+        let dir = configs_dir();
+        ConfigFiles {
+            config: Some(dir.join("systolic_ws_128x128_dev.json")),
+            mem_config: Some(dir.join("memory_configs/neupims.json")),
+            model_config: Some(dir.join("model_configs/gpt3-7B.json")),
+            sys_config: Some(dir.join("system_configs/sub-batch-on.json")),
+            cli_config: Some(PathBuf::from("trace.csv")),
+            overrides: vec![],
+        }
+    }
+
+    #[test]
+    fn test_load_repo_configs() {
+        let loaded = load_config(&repo_files()).unwrap();
+        let config = &loaded.config;
+        assert_eq!(config.model_name, "GPT3-7B");
+        assert_eq!(config.dram_type, DramType::Neupims);
+        assert_eq!(config.run_mode, RunMode::NpuPim);
+        assert_eq!(config.hbm_size, 32 << 30);
+        assert_eq!(config.hbm_act_buf_size, 512 << 20);
+        assert_eq!(config.scheduler_type, SchedulerType::Simple);
+        assert_eq!(config.request_dataset_path, "trace.csv");
+        assert!(config.sub_batch_mode);
+
+        assert!(matches!(
+            loaded.source_of("hbm_size"),
+            ConfigSource::File {
+                layer: ConfigLayer::Memory,
+                ..
+            }
+        ));
+        assert_eq!(loaded.source_of("log_dir"), &ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let mut builder = ConfigBuilder::from_files(&repo_files()).unwrap();
+        builder
+            .apply_env_vars([
+                ("NEUPIMS_CFG_MAX_BATCH_SIZE".to_string(), "64".to_string()),
+                ("NEUPIMS_CFG_N_TP".to_string(), "2".to_string()),
+                ("NEUPIMS_HOME".to_string(), "/opt/neupims".to_string()),
+                ("PATH".to_string(), "/bin".to_string()),
+            ])
+            .unwrap();
+        builder
+            .apply_command_line(&[
+                "n_tp=8".parse().unwrap(),
+                "layout = NCHW".parse().unwrap(),
+                "HBM_size=16".parse().unwrap(),
+            ])
+            .unwrap();
+        let loaded = builder.build().unwrap();
+        assert_eq!(loaded.config.hbm_size, 16 << 30);
+        assert_eq!(loaded.config.max_batch_size, 64);
+        assert_eq!(loaded.config.n_tp, 8);
+        assert_eq!(loaded.config.layout, Layout::Nchw);
+        assert_eq!(
+            loaded.source_of("max_batch_size"),
+            &ConfigSource::Env("NEUPIMS_CFG_MAX_BATCH_SIZE".to_string())
+        );
+        assert_eq!(loaded.source_of("n_tp"), &ConfigSource::CommandLine);
+    }
+
+    #[test]
+    fn test_model_preset_fills_missing_fields() {
+        let loaded = load_config(&repo_files()).unwrap();
+        let config = &loaded.config;
+        // The file's own values win over the preset.
+        assert_eq!(config.model_n_layer, 1);
+        assert_eq!(config.model_n_kv_head, 32);
+        assert_eq!(config.model_ffn_hidden, 4 * 4096);
+        assert_eq!(
+            loaded.source_of("model_ffn_hidden"),
+            &ConfigSource::Preset("GPT3-7B")
+        );
+        assert!(matches!(
+            loaded.source_of("model_n_layer"),
+            ConfigSource::File {
+                layer: ConfigLayer::Model,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unit_suffixes() {
+        let mut builder = ConfigBuilder::from_files(&repo_files()).unwrap();
+        let table = serde_json::json!({ "HBM_size": "16GiB", "core_freq": "1.2GHz" });
+        let Value::Object(table) = table else {
+            unreachable!()
+        };
+        builder
+            .merge_table(ConfigLayer::Memory, Path::new("mem.json"), table)
+            .unwrap();
+        builder
+            .apply_command_line(&["spad_size=2MiB".parse().unwrap()])
+            .unwrap();
+        let config = builder.build().unwrap().config;
+        assert_eq!(config.hbm_size, 16 << 30);
+        assert_eq!(config.core_freq, 1200);
+        assert_eq!(config.spad_size, 2048);
+
+        let mut builder = ConfigBuilder::new();
+        builder
+            .apply_command_line(&["dram_freq=1GiB".parse().unwrap()])
+            .unwrap();
+        assert!(matches!(
+            builder.build(),
+            Err(ConfigError::Field { field, .. }) if field == "dram_freq"
+        ));
+    }
+
+    #[test]
+    fn test_override_errors() {
+        assert!(matches!(
+            "max_batch_size".parse::<ConfigOverride>(),
+            Err(ConfigError::InvalidOverride(_))
+        ));
+
+        let mut builder = ConfigBuilder::new();
+        let err = builder
+            .apply_command_line(&["max_batch_sise=1".parse().unwrap()])
+            .unwrap_err();
+        assert!(
+            matches!(err, ConfigError::UnknownField { field, .. } if field == "max_batch_sise")
+        );
+
+        let mut builder = ConfigBuilder::new();
+        builder
+            .apply_command_line(&["max_batch_size=true".parse().unwrap()])
+            .unwrap();
+        assert!(matches!(
+            builder.build(),
+            Err(ConfigError::Field { field, source: ConfigSource::CommandLine, .. })
+                if field == "max_batch_size"
+        ));
+
+        let over: ConfigOverride = "max_batch_size=1".parse().unwrap();
+        assert_eq!(over.env_var_name(), "NEUPIMS_CFG_MAX_BATCH_SIZE");
+    }
+
+    #[test]
+    fn test_rerun_from_result_file() {
+        let config = SimulationConfig {
+            max_batch_size: 96,
+            ..load_config(&repo_files()).unwrap().config
+        };
+        let run_info = crate::provenance::RunInfo::new(Some(config), None);
+        let dir = crate::test_dir("rerun_from_result_file");
+        let path = dir.join("counts.json");
+        let counts = serde_json::json!({ "run_info": run_info, "last_cycle": 10 });
+        std::fs::write(&path, counts.to_string()).unwrap();
+
+        let loaded = load_config(&ConfigFiles {
+            config: Some(path.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(loaded.config.max_batch_size, 96);
+        assert_eq!(
+            crate::provenance::fingerprint(&(&Some(loaded.config), &None::<()>)),
+            run_info.config_hash
+        );
+    }
+
+    #[test]
+    fn test_comment_and_unused_keys_are_skipped() {
+        let mut builder = ConfigBuilder::from_files(&repo_files()).unwrap();
+        let table = serde_json::json!({ "__num_cores": "old value", "systolic_array_count": 8 });
+        let Value::Object(table) = table else {
+            unreachable!()
+        };
+        builder
+            .merge_table(ConfigLayer::Hardware, Path::new("hw.json"), table)
+            .unwrap();
+        let loaded = builder.build().unwrap();
+        assert!(!matches!(
+            loaded.source_of("num_cores"),
+            ConfigSource::File { path, .. } if path == Path::new("hw.json")
+        ));
+    }
+
+    #[test]
+    fn test_unknown_and_missing_fields() {
+        let mut builder = ConfigBuilder::from_files(&repo_files()).unwrap();
+        let table = serde_json::json!({ "systolic_array_size": 8 });
+        let Value::Object(table) = table else {
+            unreachable!()
+        };
+        builder
+            .merge_table(ConfigLayer::Hardware, Path::new("hw.json"), table)
+            .unwrap();
+        match builder.build() {
+            Err(ConfigError::UnknownField { field, source }) => {
+                assert_eq!(field, "systolic_array_size");
+                assert_eq!(source.to_string(), "hardware config hw.json");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let files = ConfigFiles {
+            config: None,
+            ..repo_files()
+        };
+        let err = load_config(&files).unwrap_err();
+        assert!(
+            matches!(&err, ConfigError::Deserialize(_)),
+            "unexpected error: {err}"
+        );
+        assert!(err.to_string().contains("missing field"));
+    }
+
+    #[test]
+    fn test_field_error_names_field_and_file() {
+        let mut builder = ConfigBuilder::new();
+        let table = serde_json::json!({ "num_cores": 1, "dram_channels": "many" });
+        let Value::Object(table) = table else {
+            unreachable!()
+        };
+        builder
+            .merge_table(ConfigLayer::Memory, Path::new("mem.json"), table)
+            .unwrap();
+        match builder.build() {
+            Err(ConfigError::Field { field, source, .. }) => {
+                assert_eq!(field, "dram_channels");
+                assert_eq!(source.to_string(), "memory config mem.json");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_legacy_key_overflow() {
+        let mut builder = ConfigBuilder::new();
+        let table = serde_json::json!({ "HBM_size": u64::MAX / 2 });
+        let Value::Object(table) = table else {
+            unreachable!()
+        };
+        match builder.merge_table(ConfigLayer::Memory, Path::new("mem.json"), table) {
+            Err(ConfigError::Field { field, source, .. }) => {
+                assert_eq!(field, "HBM_size");
+                assert_eq!(source.to_string(), "memory config mem.json");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        let err = builder
+            .apply_command_line(&[format!("HBM_size={}", i64::MAX).parse().unwrap()])
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::Field { .. }), "{err}");
+    }
+}
@@ -0,0 +1,329 @@
+//! Consistency checks over a loaded [`SimulationConfig`].
+//!
+//! Deserialization only guarantees that every field has the right type. The rules here catch
+//! values that are well-typed but would make the simulator misbehave later, e.g. a
+//! non-power-of-two `dram_req_size` silently breaks the address masks in the allocators.
+
+use std::{
+    ffi::{c_char, CString},
+    fmt,
+};
+
+use crate::global_config::{get_config, DramType, SimulationConfig};
+use derive_more::derive::Deref;
+
+/// A rule that a config field has to satisfy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    NonZero,
+    PowerOfTwo,
+    /// The field must be strictly less than the named field.
+    LessThan(&'static str),
+    /// The field must evenly divide the named field.
+    Divides(&'static str),
+    /// The field must be equal to the named field, for a feature the simulator does not model
+    /// yet.
+    EqualTo(&'static str),
+    /// The field may only be enabled with the given `dram_type`.
+    RequiresDramType(DramType),
+    /// The SRAM must hold two systolic-array weight tiles (double buffering).
+    FitsSystolicArray {
+        required_bytes: u64,
+    },
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::NonZero => f.write_str("must be non-zero"),
+            Rule::PowerOfTwo => f.write_str("must be a power of two"),
+            Rule::LessThan(other) => write!(f, "must be less than `{other}`"),
+            Rule::Divides(other) => write!(f, "must evenly divide `{other}`"),
+            Rule::EqualTo(other) => write!(f, "must equal `{other}` (not simulated otherwise)"),
+            Rule::RequiresDramType(dram_type) => {
+                write!(f, "can only be enabled with dram_type {dram_type:?}")
+            }
+            Rule::FitsSystolicArray { required_bytes } => write!(
+                f,
+                "must hold two weight tiles of the systolic array ({required_bytes} bytes)"
+            ),
+        }
+    }
+}
+
+/// A single rule violation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// The offending value, formatted for display.
+    pub value: String,
+    pub rule: Rule,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` = {}: {}", self.field, self.value, self.rule)
+    }
+}
+
+/// All rule violations found in one config.
+#[derive(Debug, Clone, Default, Deref)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid config field(s)", self.0.len())?;
+        for err in &self.0 {
+            write!(f, "\n  {err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl ValidationErrors {
+    fn check(&mut self, field: &'static str, value: impl fmt::Display, ok: bool, rule: Rule) {
+        if !ok {
+            self.0.push(FieldError {
+                field,
+                value: value.to_string(),
+                rule,
+            });
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// Checks the config against all rules and returns every violation found.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        for (field, value) in [
+            ("dram_req_size", self.dram_req_size),
+            ("dram_channels", self.dram_channels),
+            ("dram_page_size", self.dram_page_size),
+            ("dram_banks_per_ch", self.dram_banks_per_ch),
+        ] {
+            errors.check(field, value, value.is_power_of_two(), Rule::PowerOfTwo);
+        }
+        for (field, value) in [
+            ("num_cores", self.num_cores),
+            ("core_width", self.core_width),
+            ("core_height", self.core_height),
+            ("precision", self.precision),
+            ("n_tp", self.n_tp),
+            ("model_n_head", self.model_n_head),
+            ("model_n_embd", self.model_n_embd),
+        ] {
+            errors.check(field, value, value != 0, Rule::NonZero);
+        }
+
+        errors.check(
+            "hbm_act_buf_size",
+            self.hbm_act_buf_size,
+            self.hbm_act_buf_size < self.hbm_size,
+            Rule::LessThan("hbm_size"),
+        );
+        errors.check(
+            "sub_batch_mode",
+            self.sub_batch_mode,
+            !self.sub_batch_mode || self.dram_type == DramType::Neupims,
+            Rule::RequiresDramType(DramType::Neupims),
+        );
+        // The PIM attention kernels keep one K/V tensor per query head, so grouped-query
+        // attention is only accounted for by the footprint, not simulated.
+        errors.check(
+            "model_n_kv_head",
+            self.model_n_kv_head,
+            self.n_kv_head() == self.model_n_head,
+            Rule::EqualTo("model_n_head"),
+        );
+        if self.n_tp != 0 {
+            errors.check(
+                "n_tp",
+                self.n_tp,
+                self.model_n_head % self.n_tp == 0,
+                Rule::Divides("model_n_head"),
+            );
+            // The KV cache splits the heads over the shards without replicating any.
+            errors.check(
+                "n_tp",
+                self.n_tp,
+                self.n_kv_head() % self.n_tp == 0,
+                Rule::Divides("model_n_kv_head"),
+            );
+        }
+
+        // The C++ scratchpads span all of `sram_size`, in KiB, split in half for double
+        // buffering; `spad_size` is not read there.
+        let required_bytes =
+            self.core_width as u64 * self.core_height as u64 * self.precision as u64;
+        errors.check(
+            "sram_size",
+            self.sram_size,
+            self.sram_size as u64 * 1024 / 2 >= required_bytes,
+            Rule::FitsSystolicArray { required_bytes },
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The result of validating the global config, handed to the C++ side.
+pub struct ValidationReport {
+    messages: Vec<CString>,
+}
+
+/// Validates `config` without logging; the caller reports the violations from the report.
+///
+/// The returned report must be released with [`drop_validation_report`].
+#[no_mangle]
+pub extern "C" fn validate_config_handle(config: &SimulationConfig) -> *mut ValidationReport {
+    let messages = match config.validate() {
+        Ok(()) => vec![],
+        Err(errors) => errors
+            .iter()
+            .map(|err| CString::new(err.to_string()).expect("error messages contain no nul bytes"))
+            .collect(),
+    };
+    Box::into_raw(Box::new(ValidationReport { messages }))
+}
+
+/// Validates the global config, see [`validate_config_handle`].
+#[no_mangle]
+pub extern "C" fn validate_config() -> *mut ValidationReport {
+    validate_config_handle(get_config())
+}
+
+/// Returns the number of violations in the report, 0 if the config is valid.
+#[no_mangle]
+pub extern "C" fn validation_error_count(report: &ValidationReport) -> usize {
+    report.messages.len()
+}
+
+/// Returns the message of the `index`-th violation, or null if `index` is out of range.
+///
+/// The string is owned by the report and lives as long as it.
+#[no_mangle]
+pub extern "C" fn validation_error_message(
+    report: &ValidationReport,
+    index: usize,
+) -> *const c_char {
+    match report.messages.get(index) {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+/// Releases a report returned by [`validate_config`].
+#[no_mangle]
+pub extern "C" fn drop_validation_report(report: *mut ValidationReport) {
+    if report.is_null() {
+        return;
+    }
+    unsafe {
+        drop(Box::from_raw(report));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_config() -> SimulationConfig {
+        SimulationConfig {
+            model_n_head: 32,
+            model_n_embd: 4096,
+            n_tp: 4,
+            num_cores: 1,
+            core_width: 128,
+            core_height: 128,
+            precision: 2,
+            sram_size: 1024,
+            hbm_size: 32 << 30,
+            hbm_act_buf_size: 512 << 20,
+            dram_req_size: 64,
+            dram_channels: 32,
+            dram_page_size: 1024,
+            dram_banks_per_ch: 32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_valid_config() {
+        valid_config().validate().unwrap();
+    }
+
+    #[test]
+    fn test_reports_every_violation() {
+        let config = SimulationConfig {
+            dram_req_size: 48,
+            hbm_act_buf_size: 64 << 30,
+            sub_batch_mode: true,
+            dram_type: DramType::Newton,
+            n_tp: 3,
+            sram_size: 32,
+            model_n_embd: 0,
+            ..valid_config()
+        };
+        let errors = config.validate().unwrap_err();
+        let found: Vec<_> = errors.iter().map(|e| (e.field, e.rule.clone())).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("dram_req_size", Rule::PowerOfTwo),
+                ("model_n_embd", Rule::NonZero),
+                ("hbm_act_buf_size", Rule::LessThan("hbm_size")),
+                ("sub_batch_mode", Rule::RequiresDramType(DramType::Neupims)),
+                ("n_tp", Rule::Divides("model_n_head")),
+                ("n_tp", Rule::Divides("model_n_kv_head")),
+                (
+                    "sram_size",
+                    Rule::FitsSystolicArray {
+                        required_bytes: 128 * 128 * 2
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero_heads_are_rejected() {
+        // 0 is divisible by everything, so only the NonZero rule catches it
+        let config = SimulationConfig {
+            model_n_head: 0,
+            ..valid_config()
+        };
+        let errors = config.validate().unwrap_err();
+        let found: Vec<_> = errors.iter().map(|e| (e.field, e.rule.clone())).collect();
+        assert_eq!(found, vec![("model_n_head", Rule::NonZero)]);
+    }
+
+    #[test]
+    fn test_grouped_query_attention_is_rejected() {
+        let config = SimulationConfig {
+            model_n_kv_head: 32,
+            ..valid_config()
+        };
+        config.validate().unwrap();
+        let config = SimulationConfig {
+            model_n_kv_head: 2,
+            ..valid_config()
+        };
+        let errors = config.validate().unwrap_err();
+        let found: Vec<_> = errors.iter().map(|e| (e.field, e.rule.clone())).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("model_n_kv_head", Rule::EqualTo("model_n_head")),
+                ("n_tp", Rule::Divides("model_n_kv_head")),
+            ]
+        );
+    }
+}
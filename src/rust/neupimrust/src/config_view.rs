@@ -0,0 +1,320 @@
+//! A `#[repr(C)]` view of [`SimulationConfig`] for the C++ simulator.
+//!
+//! `bindings.h` is generated from this file, so the C++ side no longer keeps its own copy of the
+//! config layout. Plain fields are copied into [`SimulationConfigView`]; string fields stay in
+//! Rust and are read through the `config_*` getters.
+
+use std::ffi::c_char;
+
+use crate::global_config::{
+    get_config, Activation, CoreType, DramType, IcntType, Layout, RunMode, SchedulerType,
+    SimulationConfig,
+};
+
+/// A borrowed, not nul-terminated UTF-8 string.
+///
+/// Valid as long as the config it was read from; build a `std::string(ptr, len)` to keep it.
+#[repr(C)]
+pub struct FfiStr {
+    pub ptr: *const c_char,
+    pub len: usize,
+}
+
+impl From<&str> for FfiStr {
+    fn from(s: &str) -> Self {
+        FfiStr {
+            ptr: s.as_ptr().cast(),
+            len: s.len(),
+        }
+    }
+}
+
+/// All non-string fields of [`SimulationConfig`].
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct SimulationConfigView {
+    // gpt model config
+    pub model_params_b: u32,
+    pub model_block_size: u32,
+    pub model_vocab_size: u32,
+    pub model_n_layer: u32,
+    pub model_n_head: u32,
+    pub model_n_embd: u32,
+    pub model_n_kv_head: u32,  // resolved, never 0
+    pub model_ffn_hidden: u32, // resolved, never 0
+    pub model_activation: Activation,
+    pub model_tied_embeddings: bool,
+
+    /* Custom Config */
+    pub run_mode: RunMode, // NPU
+    pub sub_batch_mode: bool,
+    pub ch_load_balancing: bool,
+    pub kernel_fusion: bool,
+    pub max_batch_size: u32,
+    pub max_active_reqs: u32, // max size of (ready_queue + running_queue) in scheduler
+    pub max_seq_len: u32,
+    pub hbm_size: u64,         // HBM size in bytes
+    pub hbm_act_buf_size: u64, // HBM activation buffer size in bytes
+
+    /* Core config */
+    pub num_cores: u32,
+    pub core_type: CoreType,
+    pub core_freq: u32, // MHz
+    pub core_width: u32,
+    pub core_height: u32,
+
+    pub n_tp: u32,
+    pub n_pp: u32,
+
+    pub vector_core_count: u32,
+    pub vector_core_width: u32,
+
+    /* Vector config*/
+    pub process_bit: u32,
+
+    pub layernorm_latency: usize,
+    pub softmax_latency: usize,
+    pub add_latency: usize,
+    pub mul_latency: usize,
+    pub exp_latency: usize,
+    pub gelu_latency: usize,
+    pub add_tree_latency: usize,
+    pub scalar_sqrt_latency: usize,
+    pub scalar_add_latency: usize,
+    pub scalar_mul_latency: usize,
+
+    /* SRAM config */
+    pub sram_width: u32,
+    pub sram_size: u32,       // KiB
+    pub spad_size: u32,       // KiB
+    pub accum_spad_size: u32, // KiB
+
+    /* DRAM config */
+    pub dram_type: DramType,
+    pub dram_freq: u32, // MHz
+    pub dram_channels: u32,
+    pub dram_req_size: u32,
+
+    /* PIM config */
+    pub dram_page_size: u32, // DRAM row buffer size (in bytes)
+    pub dram_banks_per_ch: u32,
+    pub pim_comp_coverage: u32, // # params per PIM_COMP command
+
+    /* Client config */
+    pub request_input_seq_len: u32,
+    pub request_interval: u32,
+    pub request_total_cnt: u32,
+
+    /* ICNT config */
+    pub icnt_type: IcntType,
+    pub icnt_freq: u32,
+    pub icnt_latency: u32,
+
+    /* Scheduler config */
+    pub scheduler_type: SchedulerType,
+
+    /* Other configs */
+    pub precision: u32,
+    pub layout: Layout,
+}
+
+impl From<&SimulationConfig> for SimulationConfigView {
+    fn from(config: &SimulationConfig) -> Self {
+        // Destructure without `..` so that a new config field fails to compile until it is
+        // either exported here or given a getter below.
+        let SimulationConfig {
+            model_name: _,
+            model_params_b,
+            model_block_size,
+            model_vocab_size,
+            model_n_layer,
+            model_n_head,
+            model_n_embd,
+            model_n_kv_head: _,
+            model_ffn_hidden: _,
+            model_activation,
+            model_tied_embeddings,
+            run_mode,
+            sub_batch_mode,
+            ch_load_balancing,
+            kernel_fusion,
+            max_batch_size,
+            max_active_reqs,
+            max_seq_len,
+            hbm_size,
+            hbm_act_buf_size,
+            num_cores,
+            core_type,
+            core_freq,
+            core_width,
+            core_height,
+            n_tp,
+            n_pp,
+            vector_core_count,
+            vector_core_width,
+            process_bit,
+            layernorm_latency,
+            softmax_latency,
+            add_latency,
+            mul_latency,
+            exp_latency,
+            gelu_latency,
+            add_tree_latency,
+            scalar_sqrt_latency,
+            scalar_add_latency,
+            scalar_mul_latency,
+            sram_width,
+            sram_size,
+            spad_size,
+            accum_spad_size,
+            dram_type,
+            dram_freq,
+            dram_channels,
+            dram_req_size,
+            pim_config_path: _,
+            dram_page_size,
+            dram_banks_per_ch,
+            pim_comp_coverage,
+            operation_log_output_path: _,
+            log_dir: _,
+            request_input_seq_len,
+            request_interval,
+            request_total_cnt,
+            request_dataset_path: _,
+            icnt_type,
+            icnt_config_path: _,
+            icnt_freq,
+            icnt_latency,
+            scheduler_type,
+            precision,
+            layout,
+        } = *config;
+        SimulationConfigView {
+            model_params_b,
+            model_block_size,
+            model_vocab_size,
+            model_n_layer,
+            model_n_head,
+            model_n_embd,
+            model_n_kv_head: config.n_kv_head(),
+            model_ffn_hidden: config.ffn_hidden(),
+            model_activation,
+            model_tied_embeddings,
+            run_mode,
+            sub_batch_mode,
+            ch_load_balancing,
+            kernel_fusion,
+            max_batch_size,
+            max_active_reqs,
+            max_seq_len,
+            hbm_size,
+            hbm_act_buf_size,
+            num_cores,
+            core_type,
+            core_freq,
+            core_width,
+            core_height,
+            n_tp,
+            n_pp,
+            vector_core_count,
+            vector_core_width,
+            process_bit,
+            layernorm_latency,
+            softmax_latency,
+            add_latency,
+            mul_latency,
+            exp_latency,
+            gelu_latency,
+            add_tree_latency,
+            scalar_sqrt_latency,
+            scalar_add_latency,
+            scalar_mul_latency,
+            sram_width,
+            sram_size,
+            spad_size,
+            accum_spad_size,
+            dram_type,
+            dram_freq,
+            dram_channels,
+            dram_req_size,
+            dram_page_size,
+            dram_banks_per_ch,
+            pim_comp_coverage,
+            request_input_seq_len,
+            request_interval,
+            request_total_cnt,
+            icnt_type,
+            icnt_freq,
+            icnt_latency,
+            scheduler_type,
+            precision,
+            layout,
+        }
+    }
+}
+
+/// Returns the global config installed by `init_config_with_files`.
+#[no_mangle]
+pub extern "C" fn get_global_config() -> *const SimulationConfig {
+    get_config()
+}
+
+/// Copies the plain fields of `config`.
+#[no_mangle]
+pub extern "C" fn get_config_view(config: &SimulationConfig) -> SimulationConfigView {
+    config.into()
+}
+
+#[no_mangle]
+pub extern "C" fn config_model_name(config: &SimulationConfig) -> FfiStr {
+    config.model_name.as_str().into()
+}
+
+#[no_mangle]
+pub extern "C" fn config_pim_config_path(config: &SimulationConfig) -> FfiStr {
+    config.pim_config_path.as_str().into()
+}
+
+#[no_mangle]
+pub extern "C" fn config_operation_log_output_path(config: &SimulationConfig) -> FfiStr {
+    config.operation_log_output_path.as_str().into()
+}
+
+#[no_mangle]
+pub extern "C" fn config_log_dir(config: &SimulationConfig) -> FfiStr {
+    config.log_dir.as_str().into()
+}
+
+#[no_mangle]
+pub extern "C" fn config_request_dataset_path(config: &SimulationConfig) -> FfiStr {
+    config.request_dataset_path.as_str().into()
+}
+
+#[no_mangle]
+pub extern "C" fn config_icnt_config_path(config: &SimulationConfig) -> FfiStr {
+    config.icnt_config_path.as_str().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_matches_config() {
+        let config = SimulationConfig {
+            model_name: "GPT3-7B".to_string(),
+            hbm_size: 32 << 30,
+            n_pp: 2,
+            dram_type: DramType::Newton,
+            ..Default::default()
+        };
+        let view = get_config_view(&config);
+        assert_eq!(view.hbm_size, 32 << 30);
+        assert_eq!(view.n_pp, 2);
+        assert_eq!(view.dram_type, DramType::Newton);
+
+        let name = config_model_name(&config);
+        let name = unsafe { std::slice::from_raw_parts(name.ptr.cast::<u8>(), name.len) };
+        assert_eq!(name, b"GPT3-7B");
+    }
+}
@@ -0,0 +1,270 @@
+//! Energy estimation from the hardware operations counted during a run.
+//!
+//! The simulator counts [`HwOp`]s per stage. An [`EnergyTable`], read from the `[energy]` table of
+//! the settings file, gives the energy of each operation; together with the sizes of the
+//! simulation config it turns the counts into an energy breakdown per stage and per component.
+//! The DRAM model exposes no row activations, so they are estimated: every PIM header opens a row
+//! in each bank of its channel, and regular reads and writes stream through open rows, one
+//! activation per `dram_page_size` bytes.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    global_config::{try_get_config, SimulationConfig},
+    global_counts::{GlobalCountsCtx, HwCounts, HwOp, RunStage},
+    provenance::{RunInfo, WithRunInfo},
+    settings::current_energy_table,
+};
+
+/// The energy of each operation, in pJ.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergyTable {
+    /// Activating a DRAM row.
+    pub dram_act: f64,
+    /// Reading a byte from DRAM.
+    pub dram_read: f64,
+    /// Writing a byte to DRAM.
+    pub dram_write: f64,
+    /// A multiply-accumulate of a PIM bank.
+    pub pim_mac: f64,
+    /// A multiply-accumulate of the systolic array.
+    pub systolic_mac: f64,
+    /// Reading or writing a byte of a scratchpad.
+    pub sram: f64,
+    /// Moving a packet through the interconnect.
+    pub icnt_flit: f64,
+    /// Static energy of the whole system per core cycle.
+    pub static_per_cycle: f64,
+}
+
+impl Default for EnergyTable {
+    fn default() -> Self {
+        EnergyTable {
+            dram_act: 909.0,
+            dram_read: 31.2,
+            dram_write: 31.2,
+            pim_mac: 1.0,
+            systolic_mac: 0.5,
+            sram: 0.6,
+            icnt_flit: 20.0,
+            static_per_cycle: 0.0,
+        }
+    }
+}
+
+/// Energy per component, in pJ.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct EnergyBreakdown {
+    pub dram: f64,
+    pub pim: f64,
+    pub systolic: f64,
+    pub sram: f64,
+    pub icnt: f64,
+    #[serde(rename = "static")]
+    pub static_: f64,
+}
+
+impl EnergyBreakdown {
+    pub fn total(&self) -> f64 {
+        self.dram + self.pim + self.systolic + self.sram + self.icnt + self.static_
+    }
+
+    fn add(&mut self, other: &EnergyBreakdown) {
+        self.dram += other.dram;
+        self.pim += other.pim;
+        self.systolic += other.systolic;
+        self.sram += other.sram;
+        self.icnt += other.icnt;
+        self.static_ += other.static_;
+    }
+}
+
+/// An [`EnergyTable`] with the sizes of the simulated hardware.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyModel {
+    pub table: EnergyTable,
+    pub dram_req_size: u32,
+    pub dram_page_size: u32,
+    pub dram_banks_per_ch: u32,
+    pub pim_comp_coverage: u32,
+}
+
+impl EnergyModel {
+    pub fn new(table: EnergyTable, config: &SimulationConfig) -> Self {
+        EnergyModel {
+            table,
+            dram_req_size: config.dram_req_size,
+            dram_page_size: config.dram_page_size,
+            dram_banks_per_ch: config.dram_banks_per_ch,
+            pim_comp_coverage: config.pim_comp_coverage,
+        }
+    }
+
+    /// The estimated number of row activations.
+    pub fn activations(&self, counts: &HwCounts) -> u64 {
+        let bytes = (counts.get(HwOp::DramRead) + counts.get(HwOp::DramWrite))
+            * u64::from(self.dram_req_size);
+        bytes.div_ceil(u64::from(self.dram_page_size.max(1)))
+            + counts.get(HwOp::PimHeader) * u64::from(self.dram_banks_per_ch)
+    }
+
+    pub fn breakdown(&self, counts: &HwCounts) -> EnergyBreakdown {
+        let table = &self.table;
+        let count = |op| counts.get(op) as f64;
+        let req_size = f64::from(self.dram_req_size);
+        let pim_macs = count(HwOp::PimComp)
+            * f64::from(self.pim_comp_coverage)
+            * f64::from(self.dram_banks_per_ch);
+        EnergyBreakdown {
+            dram: self.activations(counts) as f64 * table.dram_act
+                + count(HwOp::DramRead) * req_size * table.dram_read
+                + count(HwOp::DramWrite) * req_size * table.dram_write,
+            pim: pim_macs * table.pim_mac,
+            systolic: count(HwOp::SystolicMac) * table.systolic_mac,
+            sram: count(HwOp::SramAccess) * req_size * table.sram,
+            icnt: count(HwOp::IcntFlit) * table.icnt_flit,
+            static_: counts.cycles as f64 * table.static_per_cycle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageEnergy {
+    pub stage: RunStage,
+    pub cycles: u64,
+    pub energy: EnergyBreakdown,
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnergyReport {
+    pub table: EnergyTable,
+    pub stages: Vec<StageEnergy>,
+    /// Over all stages.
+    pub energy: EnergyBreakdown,
+    pub total: f64,
+}
+
+impl EnergyReport {
+    pub fn new(model: &EnergyModel, ctx: &GlobalCountsCtx) -> Self {
+        let mut energy = EnergyBreakdown::default();
+        let stages = ctx
+            .stages
+            .iter()
+            .map(|(&stage, counts)| {
+                let stage_energy = model.breakdown(counts);
+                energy.add(&stage_energy);
+                StageEnergy {
+                    stage,
+                    cycles: counts.cycles,
+                    energy: stage_energy,
+                    total: stage_energy.total(),
+                }
+            })
+            .collect();
+        EnergyReport {
+            table: model.table,
+            stages,
+            energy,
+            total: energy.total(),
+        }
+    }
+}
+
+impl fmt::Display for EnergyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<8} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            "stage", "dram", "pim", "systolic", "sram", "icnt", "static", "total (nJ)"
+        )?;
+        let mut row = |name: &str, energy: &EnergyBreakdown| {
+            writeln!(
+                f,
+                "{:<8} {:>12.1} {:>12.1} {:>12.1} {:>12.1} {:>12.1} {:>12.1} {:>12.1}",
+                name,
+                energy.dram / 1e3,
+                energy.pim / 1e3,
+                energy.systolic / 1e3,
+                energy.sram / 1e3,
+                energy.icnt / 1e3,
+                energy.static_ / 1e3,
+                energy.total() / 1e3,
+            )
+        };
+        for stage in &self.stages {
+            row(&format!("{:?}", stage.stage), &stage.energy)?;
+        }
+        row("all", &self.energy)
+    }
+}
+
+/// 根据计数估算每个阶段和每个部件的能耗，连同 [`RunInfo`] 保存到输出目录下的 `energy.json`
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`；配置未初始化时不写入，返回`false`
+#[no_mangle]
+pub extern "C" fn save_energy_report(ctx: &mut GlobalCountsCtx) -> bool {
+    let Some(config) = try_get_config() else {
+        error!("配置未初始化，无法估算能耗");
+        return false;
+    };
+    let model = EnergyModel::new(current_energy_table(), config);
+    let report = EnergyReport::new(&model, ctx);
+    info!("能耗估算:\n{report}");
+    let path = ctx.out_dir.join("energy.json");
+    match WithRunInfo::new(RunInfo::current(), &report).write_to_file(&path) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("无法写入{}: {err}", path.display());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_counts::{add_hw_ops, update_last_cycle, update_stage};
+
+    #[test]
+    fn test_energy_breakdown() {
+        let mut ctx = GlobalCountsCtx::default();
+        add_hw_ops(&mut ctx, HwOp::DramRead, 4, std::ptr::null());
+        add_hw_ops(&mut ctx, HwOp::SystolicMac, 1000, std::ptr::null());
+        update_last_cycle(&mut ctx, 10);
+        update_stage(&mut ctx, RunStage::B, 10);
+        add_hw_ops(&mut ctx, HwOp::PimHeader, 1, std::ptr::null());
+        add_hw_ops(&mut ctx, HwOp::PimComp, 2, std::ptr::null());
+        update_last_cycle(&mut ctx, 30);
+
+        let model = EnergyModel {
+            table: EnergyTable {
+                static_per_cycle: 1.0,
+                ..Default::default()
+            },
+            dram_req_size: 32,
+            dram_page_size: 1024,
+            dram_banks_per_ch: 16,
+            pim_comp_coverage: 8,
+        };
+        let report = EnergyReport::new(&model, &ctx);
+        let a = &report.stages[0];
+        assert_eq!((a.stage, a.cycles), (RunStage::A, 10));
+        // 128 bytes read: one activation
+        assert_eq!(a.energy.dram, 909.0 + 128.0 * 31.2);
+        assert_eq!(a.energy.systolic, 500.0);
+        assert_eq!(a.energy.static_, 10.0);
+        let b = &report.stages[1];
+        assert_eq!(b.cycles, 20);
+        assert_eq!(b.energy.dram, 16.0 * 909.0);
+        assert_eq!(b.energy.pim, 2.0 * 8.0 * 16.0);
+        assert_eq!(report.energy.static_, 30.0);
+        assert_eq!(report.total, a.total + b.total);
+    }
+}
@@ -0,0 +1,291 @@
+//! Incremental logging of the simulation event stream.
+//!
+//! Instead of collecting every [`Event`] in memory until the end of the run, the simulator writes
+//! each event as one JSON line to `events.jsonl` as soon as it happens. The writer is buffered and
+//! flushed at the end of every stage, so a crash loses at most the events of the current stage.
+//! An [`EventFilter`], read from the `[event_log]` table of the settings file, drops the event
+//! types and stages that are not of interest before they are written.
+//!
+//! `counts.json` only records a summary of the log with the path of the file. Deserializing it
+//! gives a closed log that reads its events back from that file.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::error;
+
+use crate::global_counts::{Event, EventType, RunStage};
+
+/// The kind of an [`EventType`], without its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub enum EventKind {
+    MemEventStart,
+    MemEventEnd,
+    StageStart,
+    StageEnd,
+    NpuStart,
+    PimStart,
+    NpuFinished,
+    PimFinished,
+}
+
+impl EventType {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            EventType::MemEventStart(_) => EventKind::MemEventStart,
+            EventType::MemEventEnd(_) => EventKind::MemEventEnd,
+            EventType::StageStart => EventKind::StageStart,
+            EventType::StageEnd => EventKind::StageEnd,
+            EventType::NpuStart => EventKind::NpuStart,
+            EventType::PimStart => EventKind::PimStart,
+            EventType::NpuFinished => EventKind::NpuFinished,
+            EventType::PimFinished => EventKind::PimFinished,
+        }
+    }
+}
+
+/// Which events are logged. An empty list keeps everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventFilter {
+    /// Only log events of these kinds.
+    pub types: Vec<EventKind>,
+    /// Only log events that happen during these stages.
+    pub stages: Vec<RunStage>,
+}
+
+impl EventFilter {
+    pub fn accepts(&self, event: &Event) -> bool {
+        (self.types.is_empty() || self.types.contains(&event.event.kind()))
+            && (self.stages.is_empty() || self.stages.contains(&event.stage))
+    }
+}
+
+#[derive(Debug)]
+enum EventSink {
+    /// Kept in memory, for tests and library users that analyze the events directly.
+    Memory(Vec<Event>),
+    /// Streamed as JSON lines.
+    File {
+        path: PathBuf,
+        writer: BufWriter<File>,
+    },
+    /// Read back from a report: the events are in `path`, if they were streamed.
+    Closed { path: Option<PathBuf> },
+}
+
+/// The destination of the event stream. Serializes as a summary of what was logged.
+#[derive(Debug)]
+pub struct EventLog {
+    filter: EventFilter,
+    sink: EventSink,
+    /// Events written so far.
+    written: u64,
+    /// Events dropped by the filter so far.
+    filtered: u64,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::in_memory(EventFilter::default())
+    }
+}
+
+impl EventLog {
+    pub fn in_memory(filter: EventFilter) -> Self {
+        EventLog {
+            filter,
+            sink: EventSink::Memory(Vec::new()),
+            written: 0,
+            filtered: 0,
+        }
+    }
+
+    /// Creates `path`, truncating it if it exists, and streams the events into it.
+    pub fn to_file(path: impl Into<PathBuf>, filter: EventFilter) -> std::io::Result<Self> {
+        let path = path.into();
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok(EventLog {
+            filter,
+            sink: EventSink::File { path, writer },
+            written: 0,
+            filtered: 0,
+        })
+    }
+
+    /// Logs `event` unless the filter drops it. Every stage end flushes the writer, even one the
+    /// filter drops, so the file is complete up to the last finished stage.
+    pub fn push(&mut self, event: Event) {
+        let stage_end = matches!(event.event, EventType::StageEnd);
+        if self.filter.accepts(&event) {
+            self.written += 1;
+            self.write(&event);
+        } else {
+            self.filtered += 1;
+        }
+        if let (true, EventSink::File { path, writer }) = (stage_end, &mut self.sink) {
+            if let Err(err) = writer.flush() {
+                error!("无法写入事件到{}: {err}", path.display());
+            }
+        }
+    }
+
+    fn write(&mut self, event: &Event) {
+        match &mut self.sink {
+            EventSink::Memory(events) => events.push(event.clone()),
+            EventSink::File { path, writer } => {
+                let result = serde_json::to_writer(&mut *writer, event)
+                    .map_err(std::io::Error::from)
+                    .and_then(|()| writer.write_all(b"\n"));
+                if let Err(err) = result {
+                    error!("无法写入事件到{}: {err}", path.display());
+                }
+            }
+            EventSink::Closed { .. } => error!("事件流已关闭，无法写入"),
+        }
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.sink {
+            EventSink::Memory(_) | EventSink::Closed { .. } => Ok(()),
+            EventSink::File { writer, .. } => writer.flush(),
+        }
+    }
+
+    /// The number of events written so far.
+    pub fn len(&self) -> u64 {
+        self.written
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// The number of events dropped by the filter so far.
+    pub fn filtered(&self) -> u64 {
+        self.filtered
+    }
+
+    /// All the events written so far, read back from the file if the log is streamed. A streamed
+    /// log only holds what was flushed, so a log still being written needs a [`EventLog::flush`]
+    /// first.
+    pub fn events(&self) -> std::io::Result<Vec<Event>> {
+        match &self.sink {
+            EventSink::Memory(events) => Ok(events.clone()),
+            EventSink::File { path, .. } | EventSink::Closed { path: Some(path) } => {
+                read_events(path)
+            }
+            EventSink::Closed { path: None } => Ok(Vec::new()),
+        }
+    }
+
+    /// The file the events are streamed to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.sink {
+            EventSink::File { path, .. } | EventSink::Closed { path: Some(path) } => Some(path),
+            EventSink::Memory(_) | EventSink::Closed { path: None } => None,
+        }
+    }
+
+    /// Points a closed log whose file does not exist at the file of the same name in `dir`, for
+    /// reports that were moved or written relative to another working directory.
+    pub fn relocate(&mut self, dir: &Path) {
+        if let EventSink::Closed { path: Some(path) } = &mut self.sink {
+            if let Some(name) = path.file_name().filter(|_| !path.exists()) {
+                *path = dir.join(name);
+            }
+        }
+    }
+}
+
+impl Serialize for EventLog {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Summary<'a> {
+            path: Option<&'a Path>,
+            written: u64,
+            filtered: u64,
+            filter: &'a EventFilter,
+        }
+        Summary {
+            path: self.path(),
+            written: self.written,
+            filtered: self.filtered,
+            filter: &self.filter,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EventLog {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Summary {
+            path: Option<PathBuf>,
+            written: u64,
+            filtered: u64,
+            filter: EventFilter,
+        }
+        let summary = Summary::deserialize(deserializer)?;
+        Ok(EventLog {
+            filter: summary.filter,
+            sink: EventSink::Closed { path: summary.path },
+            written: summary.written,
+            filtered: summary.filtered,
+        })
+    }
+}
+
+/// Reads an `events.jsonl` written by [`EventLog`].
+pub fn read_events(path: &Path) -> std::io::Result<Vec<Event>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_counts::MemOp;
+
+    fn event(cycle: u64, stage: RunStage, event: EventType) -> Event {
+        Event {
+            cycle,
+            stage,
+            event,
+        }
+    }
+
+    #[test]
+    fn test_stream_and_filter() {
+        let dir = crate::test_dir("stream_and_filter");
+        let path = dir.join("events.jsonl");
+        let filter = EventFilter {
+            types: vec![EventKind::StageStart, EventKind::MemEventStart],
+            stages: vec![RunStage::B],
+        };
+        let mut log = EventLog::to_file(&path, filter).unwrap();
+        log.push(event(1, RunStage::A, EventType::StageStart));
+        log.push(event(2, RunStage::B, EventType::StageStart));
+        log.push(event(3, RunStage::B, EventType::MemEventStart(MemOp::Load)));
+        log.push(event(4, RunStage::B, EventType::MemEventEnd(MemOp::Load)));
+        // the stage end is filtered out, but still flushes what was written before it
+        log.push(event(5, RunStage::B, EventType::StageEnd));
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let events = log.events().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].cycle, 3);
+        let summary = serde_json::to_value(&log).unwrap();
+        assert_eq!(summary["written"], 2);
+        assert_eq!(summary["filtered"], 3);
+        assert_eq!(log.filtered(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
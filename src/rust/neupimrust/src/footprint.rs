@@ -0,0 +1,184 @@
+//! HBM footprint of a model on one tensor-parallel shard.
+//!
+//! Sizes are derived from the model fields of [`SimulationConfig`], `precision` and `n_tp`, for the
+//! `model_n_layer` layers that are simulated. They are checked against `hbm_size`, which holds the
+//! weights, the activation buffer and the KV cache, and against `hbm_act_buf_size`.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{global_config::SimulationConfig, units::format_bytes};
+
+/// Memory needs of one shard.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Footprint {
+    pub weight_bytes_per_shard: u64,
+    pub kv_bytes_per_token: u64,
+    /// KV cache of one request at `max_seq_len`.
+    pub kv_bytes_per_request: u64,
+    /// Activations of one decoding step at `max_batch_size`.
+    pub activation_bytes: u64,
+    /// HBM left for the KV cache after the weights and the activation buffer.
+    pub kv_capacity_bytes: u64,
+    /// How many requests of `max_seq_len` tokens fit in the KV cache at once.
+    pub max_concurrent_requests: u64,
+    /// Every way in which the model does not fit.
+    pub problems: Vec<String>,
+}
+
+impl Footprint {
+    pub fn fits(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl SimulationConfig {
+    /// Computes the footprint of the model on one shard and checks it against the HBM.
+    pub fn footprint(&self) -> Footprint {
+        let n_tp = self.n_tp.max(1) as u64;
+        let precision = self.precision as u64;
+        let embd = self.model_n_embd as u64;
+
+        let weight_bytes_per_shard = (self.model_params() * precision).div_ceil(n_tp);
+
+        // KV heads are split evenly over the shards, as in the C++ KV cache; validation rejects
+        // an `n_tp` that does not divide them.
+        let kv_heads_per_shard = self.n_kv_head() as u64 / n_tp;
+        let kv_bytes_per_token =
+            2 * self.model_n_layer as u64 * kv_heads_per_shard * self.head_dim() as u64 * precision;
+        let kv_bytes_per_request = kv_bytes_per_token * self.max_seq_len as u64;
+
+        // One token per request: the residual stream next to the widest projection output,
+        // plus each request's attention scores over its whole context.
+        let kv_width = (self.n_kv_head() * self.head_dim()) as u64;
+        let qkv_width = (embd + 2 * kv_width) / n_tp;
+        let ffn_matrices = if self.model_activation.is_gated() {
+            2
+        } else {
+            1
+        };
+        let fc1_width = ffn_matrices * self.ffn_hidden() as u64 / n_tp;
+        let per_token = 2 * embd + qkv_width.max(fc1_width);
+        let scores = (self.model_n_head as u64).div_ceil(n_tp) * self.max_seq_len as u64;
+        let activation_bytes = self.max_batch_size as u64 * (per_token + scores) * precision;
+
+        let kv_capacity_bytes = self
+            .hbm_size
+            .saturating_sub(self.hbm_act_buf_size)
+            .saturating_sub(weight_bytes_per_shard);
+        let max_concurrent_requests = kv_capacity_bytes
+            .checked_div(kv_bytes_per_request)
+            .unwrap_or(u64::MAX);
+
+        let mut problems = vec![];
+        if weight_bytes_per_shard + self.hbm_act_buf_size > self.hbm_size {
+            problems.push(format!(
+                "weights ({}) and activation buffer ({}) exceed hbm_size ({})",
+                format_bytes(weight_bytes_per_shard),
+                format_bytes(self.hbm_act_buf_size),
+                format_bytes(self.hbm_size)
+            ));
+        }
+        if activation_bytes > self.hbm_act_buf_size {
+            problems.push(format!(
+                "activations at max_batch_size {} ({}) exceed hbm_act_buf_size ({})",
+                self.max_batch_size,
+                format_bytes(activation_bytes),
+                format_bytes(self.hbm_act_buf_size)
+            ));
+        }
+        if max_concurrent_requests < self.max_batch_size as u64 {
+            problems.push(format!(
+                "the KV cache holds {max_concurrent_requests} requests of max_seq_len {}, fewer than max_batch_size {}",
+                self.max_seq_len, self.max_batch_size
+            ));
+        }
+
+        Footprint {
+            weight_bytes_per_shard,
+            kv_bytes_per_token,
+            kv_bytes_per_request,
+            activation_bytes,
+            kv_capacity_bytes,
+            max_concurrent_requests,
+            problems,
+        }
+    }
+}
+
+impl fmt::Display for Footprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, bytes) in [
+            ("weights per shard", self.weight_bytes_per_shard),
+            ("KV cache per token", self.kv_bytes_per_token),
+            ("KV cache per request", self.kv_bytes_per_request),
+            ("activations", self.activation_bytes),
+            ("KV cache capacity", self.kv_capacity_bytes),
+        ] {
+            writeln!(f, "{name:<24} {}", format_bytes(bytes))?;
+        }
+        write!(
+            f,
+            "{:<24} {}",
+            "max concurrent requests", self.max_concurrent_requests
+        )?;
+        for problem in &self.problems {
+            write!(f, "\ndoes not fit: {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_config::Activation;
+
+    fn llama2_70b() -> SimulationConfig {
+        SimulationConfig {
+            model_vocab_size: 32000,
+            model_n_layer: 80,
+            model_n_head: 64,
+            model_n_kv_head: 8,
+            model_n_embd: 8192,
+            model_ffn_hidden: 28672,
+            model_activation: Activation::Swiglu,
+            precision: 2,
+            n_tp: 8,
+            max_seq_len: 4096,
+            max_batch_size: 32,
+            hbm_size: 80 << 30,
+            hbm_act_buf_size: 1 << 30,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_llama2_70b_on_eight_shards() {
+        let footprint = llama2_70b().footprint();
+        // 69 billion weights in fp16 over 8 shards.
+        assert_eq!(footprint.weight_bytes_per_shard / 1_000_000_000, 17);
+        // One KV head per shard.
+        assert_eq!(footprint.kv_bytes_per_token, 2 * 80 * 128 * 2);
+        assert_eq!(footprint.kv_bytes_per_request, 2 * 80 * 128 * 2 * 4096);
+        assert_eq!(
+            footprint.max_concurrent_requests,
+            footprint.kv_capacity_bytes / footprint.kv_bytes_per_request
+        );
+        assert!(footprint.fits(), "{footprint}");
+    }
+
+    #[test]
+    fn test_reports_problems() {
+        let config = SimulationConfig {
+            n_tp: 1,
+            hbm_size: 32 << 30,
+            ..llama2_70b()
+        };
+        let footprint = config.footprint();
+        assert_eq!(footprint.kv_capacity_bytes, 0);
+        assert_eq!(footprint.max_concurrent_requests, 0);
+        assert_eq!(footprint.problems.len(), 2, "{footprint}");
+    }
+}
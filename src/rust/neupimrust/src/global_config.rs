@@ -1,131 +1,565 @@
+//! The simulation configuration.
+//!
+//! Rust components receive their config explicitly as an `Arc<SimulationConfig>`, so several
+//! configurations can live in one process. The process-global config below only exists for the
+//! C++ simulator, which still reads a single config through the FFI.
 
+use std::sync::{Arc, OnceLock};
+
+use schemars::JsonSchema;
+use tracing::warn;
+
+use crate::{
+    registry::{registered_serde, Choice, Registered},
+    units,
+};
+
+static CONFIG: OnceLock<Arc<SimulationConfig>> = OnceLock::new();
+
+/// Returns the global config used by the C++ side.
+///
+/// If no config has been installed with [`set_config`], falls back to reading `sjq_config.toml`
+/// from the current directory.
 pub fn get_config() -> &'static SimulationConfig {
-    lazy_static::lazy_static! {
-        static ref CONFIG: SimulationConfig = {
-            let config_str = std::fs::read_to_string("sjq_config.toml").expect("Unable to read file");
-            toml::from_str(&config_str).expect("Failed to parse TOML")
-        };
+    get_config_handle_ref()
+}
+
+/// Returns the global config if one has been installed, without the `sjq_config.toml` fallback.
+pub fn try_get_config() -> Option<&'static SimulationConfig> {
+    CONFIG.get().map(|config| config.as_ref())
+}
+
+/// Returns a handle to the global config, for passing it on to Rust components.
+pub fn get_config_handle() -> Arc<SimulationConfig> {
+    get_config_handle_ref().clone()
+}
+
+fn get_config_handle_ref() -> &'static Arc<SimulationConfig> {
+    CONFIG.get_or_init(|| {
+        let config_str = std::fs::read_to_string("sjq_config.toml").expect("Unable to read file");
+        Arc::new(toml::from_str(&config_str).expect("Failed to parse TOML"))
+    })
+}
+
+/// Installs `config` as the global config. Returns `false` if a config was already installed.
+pub fn set_config(config: SimulationConfig) -> bool {
+    let installed = CONFIG.set(Arc::new(config)).is_ok();
+    if !installed {
+        warn!("global config already initialized, ignoring the new one");
     }
-    &CONFIG
+    installed
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// The configuration of one simulation, merged from the hardware, memory, model, system and
+/// client config files.
+///
+/// Only fields with a meaningful default may be left out; every other field has to be set by one
+/// of the files, so a forgotten hardware parameter is an error rather than a silent 0.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct SimulationConfig {
     // gpt model config
+    /// Model name; a built-in preset name fills in the other model fields
     pub model_name: String,
+    /// Number of model parameters, in billions
     pub model_params_b: u32,
+    /// Maximum context length of the model
+    #[serde(default)]
     pub model_block_size: u32,
+    /// Vocabulary size
     pub model_vocab_size: u32,
+    /// Number of simulated decoder layers
     pub model_n_layer: u32,
+    /// Number of attention heads
     pub model_n_head: u32,
+    /// Embedding size
     pub model_n_embd: u32,
+    /// Number of KV heads for GQA/MQA; 0 means one KV head per attention head. Only the
+    /// footprint uses it so far, validation rejects any other value than `model_n_head`
+    #[serde(default)]
+    pub model_n_kv_head: u32,
+    /// FFN hidden size; 0 means 4 * model_n_embd
+    #[serde(default)]
+    pub model_ffn_hidden: u32,
+    /// FFN activation function
+    #[serde(default)]
+    pub model_activation: Activation,
+    /// Whether the LM head shares the token embedding
+    #[serde(default)]
+    pub model_tied_embeddings: bool,
 
     /* Custom Config */
-    pub run_mode: RunMode, // NPU
+    /// Whether attention runs on the NPU or on the PIM
+    pub run_mode: RunMode,
+    /// Sub-batch interleaving between NPU and PIM, only available with dram_type neupims
     pub sub_batch_mode: bool,
+    /// Greedy min-load assignment of requests to PIM channels instead of round-robin
     pub ch_load_balancing: bool,
+    /// Whether kernel fusion is applied
     pub kernel_fusion: bool,
+    /// Maximum batch size
     pub max_batch_size: u32,
-    pub max_active_reqs: u32, // max size of (ready_queue + running_queue) in scheduler
+    /// Maximum size of (ready_queue + running_queue) in the scheduler
+    pub max_active_reqs: u32,
+    /// Maximum sequence length
     pub max_seq_len: u32,
-    pub hbm_size: u64,         // HBM size in bytes
-    pub hbm_act_buf_size: u64, // HBM activation buffer size in bytes
+    /// HBM size, in bytes
+    #[serde(deserialize_with = "units::bytes")]
+    #[schemars(schema_with = "units::bytes_schema")]
+    pub hbm_size: u64,
+    /// HBM activation buffer size, in bytes
+    #[serde(deserialize_with = "units::bytes")]
+    #[schemars(schema_with = "units::bytes_schema")]
+    pub hbm_act_buf_size: u64,
 
     /* Core config */
+    /// Number of NPU cores
     pub num_cores: u32,
+    /// Dataflow of the systolic arrays
     pub core_type: CoreType,
+    /// Core frequency, in MHz
+    #[serde(deserialize_with = "units::mhz")]
+    #[schemars(schema_with = "units::mhz_schema")]
     pub core_freq: u32,
+    /// Systolic array width
     pub core_width: u32,
+    /// Systolic array height
     pub core_height: u32,
 
+    /// Degree of tensor parallelism
     pub n_tp: u32,
+    /// Degree of pipeline parallelism
+    #[serde(default)]
+    pub n_pp: u32,
 
+    /// Number of vector units per core
     pub vector_core_count: u32,
+    /// Number of lanes per vector unit
     pub vector_core_width: u32,
 
     /* Vector config*/
+    /// Bit width of vector operands
     pub process_bit: u32,
 
+    /// LayerNorm latency, in cycles
+    #[serde(default)]
     pub layernorm_latency: usize,
+    /// Softmax latency, in cycles
+    #[serde(default)]
     pub softmax_latency: usize,
+    /// Vector add latency, in cycles
     pub add_latency: usize,
+    /// Vector multiply latency, in cycles
     pub mul_latency: usize,
+    /// Vector exp latency, in cycles
     pub exp_latency: usize,
+    /// GELU latency, in cycles
     pub gelu_latency: usize,
+    /// Adder tree latency, in cycles
     pub add_tree_latency: usize,
+    /// Scalar sqrt latency, in cycles
     pub scalar_sqrt_latency: usize,
+    /// Scalar add latency, in cycles
     pub scalar_add_latency: usize,
+    /// Scalar multiply latency, in cycles
     pub scalar_mul_latency: usize,
 
     /* SRAM config */
+    /// SRAM access width, in bytes
     pub sram_width: u32,
+    /// SRAM size, in KiB
+    #[serde(deserialize_with = "units::kib")]
+    #[schemars(schema_with = "units::kib_schema")]
     pub sram_size: u32,
+    /// Scratchpad size, in KiB, split in half for double buffering; the C++ simulator sizes its
+    /// scratchpads from `sram_size` instead
+    #[serde(default, deserialize_with = "units::kib")]
+    #[schemars(schema_with = "units::kib_schema")]
     pub spad_size: u32,
+    /// Accumulator scratchpad size, in KiB; the C++ simulator uses `sram_size` instead
+    #[serde(default, deserialize_with = "units::kib")]
+    #[schemars(schema_with = "units::kib_schema")]
     pub accum_spad_size: u32,
 
     /* DRAM config */
+    /// Memory type
     pub dram_type: DramType,
+    /// DRAM frequency, in MHz
+    #[serde(deserialize_with = "units::mhz")]
+    #[schemars(schema_with = "units::mhz_schema")]
     pub dram_freq: u32,
+    /// Number of DRAM channels
     pub dram_channels: u32,
+    /// DRAM access granularity, in bytes
     pub dram_req_size: u32,
 
     /* PIM config */
+    /// DRAM or PIM hardware specification (`.ini`)
+    #[serde(default)]
     pub pim_config_path: String,
-    pub dram_page_size: u32, // DRAM row buffer size (in bytes)
+    /// DRAM row buffer size, in bytes
+    pub dram_page_size: u32,
+    /// Number of DRAM banks per channel
     pub dram_banks_per_ch: u32,
-    pub pim_comp_coverage: u32, // # params per PIM_COMP command
+    /// Number of parameters per PIM_COMP command
+    pub pim_comp_coverage: u32,
 
     /* Log config */
+    /// Where the per-operation log is written
+    #[serde(default)]
     pub operation_log_output_path: String,
+    /// Directory receiving the simulation logs
+    #[serde(default)]
     pub log_dir: String,
 
     /* Client config */
+    /// Input sequence length of generated requests
+    #[serde(default)]
     pub request_input_seq_len: u32,
+    /// Cycles between generated requests
+    #[serde(default)]
     pub request_interval: u32,
+    /// Number of generated requests
+    #[serde(default)]
     pub request_total_cnt: u32,
+    /// Request trace, (seq_len, pim_ch_idx) per request
+    #[serde(default)]
     pub request_dataset_path: String,
 
     /* ICNT config */
+    /// Interconnect model
     pub icnt_type: IcntType,
+    /// Booksim2 configuration file
+    #[serde(default)]
     pub icnt_config_path: String,
+    /// Interconnect frequency, in MHz
     pub icnt_freq: u32,
+    /// Interconnect latency, in cycles
+    #[serde(default)]
     pub icnt_latency: u32,
 
     /* Scheduler config */
-    pub scheduler_type: String,
+    /// Request scheduling policy
+    pub scheduler_type: SchedulerType,
 
     /* Other configs */
+    /// Bytes per element
     pub precision: u32,
-    pub layout: String,
+    /// Activation tensor layout
+    pub layout: Layout,
 }
 
 impl SimulationConfig {
     pub fn align_address(&self, addr: u64) -> u64 {
         addr - (addr % self.dram_req_size as u64)
     }
+
+    /// The names of all fields, as spelled in config files.
+    pub fn field_names() -> Vec<String> {
+        Self::default_fields().keys().cloned().collect()
+    }
+
+    /// Every field with its [`Default`] value, as spelled in config files.
+    pub fn default_fields() -> serde_json::Map<String, serde_json::Value> {
+        match serde_json::to_value(SimulationConfig::default()) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => unreachable!("SimulationConfig serializes to a map"),
+        }
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// The dataflow of the systolic arrays, looked up by name in its registry.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum CoreType {
     SystolicOs,
+    #[default]
     SystolicWs,
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl Registered for CoreType {
+    const KIND: &'static str = "core_type";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: CoreType::SystolicOs,
+                name: "systolic_os",
+                aliases: &["SystolicOs"],
+                description: "output-stationary systolic array",
+            },
+            Choice {
+                value: CoreType::SystolicWs,
+                name: "systolic_ws",
+                aliases: &["SystolicWs"],
+                description: "weight-stationary systolic array",
+            },
+        ]
+    }
+}
+
+registered_serde!(CoreType);
+
+/// The memory type, looked up by name in its registry.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum DramType {
     Dram,
     Newton,
+    #[default]
     Neupims,
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl Registered for DramType {
+    const KIND: &'static str = "dram_type";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: DramType::Dram,
+                name: "dram",
+                aliases: &[],
+                description: "plain HBM",
+            },
+            Choice {
+                value: DramType::Newton,
+                name: "newton",
+                aliases: &[],
+                description: "Newton PIM",
+            },
+            Choice {
+                value: DramType::Neupims,
+                name: "neupims",
+                aliases: &[],
+                description: "dual row buffered PIM",
+            },
+        ]
+    }
+}
+
+registered_serde!(DramType);
+
+/// The interconnect model, looked up by name in its registry.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum IcntType {
+    #[default]
     Simple,
     Booksim2,
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl Registered for IcntType {
+    const KIND: &'static str = "icnt_type";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: IcntType::Simple,
+                name: "simple",
+                aliases: &[],
+                description: "fixed-latency interconnect",
+            },
+            Choice {
+                value: IcntType::Booksim2,
+                name: "booksim2",
+                aliases: &[],
+                description: "cycle-level Booksim2 network, configured by icnt_config_path",
+            },
+        ]
+    }
+}
+
+registered_serde!(IcntType);
+
+/// Where attention runs, looked up by name in its registry.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum RunMode {
     NpuOnly,
+    #[default]
     NpuPim,
 }
+
+impl Registered for RunMode {
+    const KIND: &'static str = "run_mode";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: RunMode::NpuOnly,
+                name: "npu",
+                aliases: &["npu_only", "NpuOnly"],
+                description: "everything runs on the NPU",
+            },
+            Choice {
+                value: RunMode::NpuPim,
+                name: "npu+pim",
+                aliases: &["npu_pim", "NpuPim"],
+                description: "attention runs on the PIM",
+            },
+        ]
+    }
+}
+
+registered_serde!(RunMode);
+
+/// The request scheduling policy, looked up by name in its registry.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SchedulerType {
+    #[default]
+    Simple,
+    Neupims,
+}
+
+impl Registered for SchedulerType {
+    const KIND: &'static str = "scheduler_type";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: SchedulerType::Simple,
+                name: "simple",
+                aliases: &["orca"],
+                description: "Orca-style iteration-level scheduling",
+            },
+            Choice {
+                value: SchedulerType::Neupims,
+                name: "neupims",
+                aliases: &[],
+                description: "NeuPIMs sub-batch interleaving between NPU and PIM",
+            },
+        ]
+    }
+}
+
+registered_serde!(SchedulerType);
+
+/// The activation tensor layout, looked up by name in its registry.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Layout {
+    Nchw,
+    #[default]
+    Nhwc,
+}
+
+impl Registered for Layout {
+    const KIND: &'static str = "layout";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: Layout::Nchw,
+                name: "NCHW",
+                aliases: &[],
+                description: "channels before spatial dimensions",
+            },
+            Choice {
+                value: Layout::Nhwc,
+                name: "NHWC",
+                aliases: &[],
+                description: "channels last",
+            },
+        ]
+    }
+}
+
+registered_serde!(Layout);
+
+/// The activation function of the feed-forward block.
+/// cbindgen:rename-all=ScreamingSnakeCase
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Activation {
+    #[default]
+    Gelu,
+    Relu,
+    Swiglu,
+}
+
+impl Activation {
+    /// Whether the first FFN projection is split into a gate and an up projection.
+    pub fn is_gated(self) -> bool {
+        self == Activation::Swiglu
+    }
+}
+
+impl Registered for Activation {
+    const KIND: &'static str = "model_activation";
+
+    fn registry() -> &'static [Choice<Self>] {
+        &[
+            Choice {
+                value: Activation::Gelu,
+                name: "gelu",
+                aliases: &["gelu_new"],
+                description: "GELU, as in GPT-3",
+            },
+            Choice {
+                value: Activation::Relu,
+                name: "relu",
+                aliases: &[],
+                description: "ReLU, as in OPT",
+            },
+            Choice {
+                value: Activation::Swiglu,
+                name: "swiglu",
+                aliases: &["silu"],
+                description: "gated SiLU, as in LLaMA and Mistral",
+            },
+        ]
+    }
+}
+
+registered_serde!(Activation);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registered_names() {
+        for choice in SchedulerType::registry() {
+            let json = serde_json::to_value(choice.value).unwrap();
+            assert_eq!(json, choice.name);
+            for alias in choice.aliases {
+                let parsed: SchedulerType =
+                    serde_json::from_value(alias.to_string().into()).unwrap();
+                assert_eq!(parsed, choice.value);
+            }
+        }
+        assert_eq!(Layout::from_name("nhwc"), Ok(Layout::Nhwc));
+    }
+
+    #[test]
+    fn test_unknown_run_mode_is_rejected() {
+        let err = serde_json::from_str::<RunMode>(r#""npu_pimm""#).unwrap_err();
+        assert!(
+            err.to_string().starts_with("unknown run_mode `npu_pimm`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_unknown_name_lists_choices() {
+        let err = serde_json::from_str::<SchedulerType>(r#""fifo""#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown scheduler_type `fifo`, valid choices are: simple (alias: orca), neupims"
+        );
+    }
+}
@@ -1,93 +1,256 @@
-use derive_more::derive::AddAssign;
-use serde::Serialize;
-use std::{collections::BTreeMap, fs::File};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ffi::c_char, path::PathBuf};
 use tracing::{error, info};
 
-#[derive(Serialize)]
-pub enum MemStatus {
-    Idle(u64),
-    Busy(u64),
+use crate::activity::{ActivityTracker, Transition};
+pub use crate::activity::{Cycle, MemStatus};
+use crate::config_loader::c_path;
+use crate::event_log::EventLog;
+use crate::histogram::HistogramStrategy;
+use crate::overlap::OverlapTracker;
+use crate::report::output_dir;
+use crate::sampler::Sampler;
+use crate::settings::{current_event_filter, current_histogram_strategy, current_sampling};
+
+#[derive(Serialize, Deserialize)]
+pub struct GlobalCountsCtx {
+    // the statistics
+    /// the last cycle
+    pub last_cycle: u64,
+
+    /// 所有核心汇总的每种操作的累计次数、busy/idle 时间及其时间间隔统计
+    pub activity: ActivityTracker<MemOp>,
+    /// 每个核心的统计，以核心编号为键
+    pub cores: BTreeMap<u32, ActivityTracker<MemOp>>,
+    /// 每个DRAM通道的读写统计，以通道编号为键，时间以DRAM周期计
+    pub channels: BTreeMap<u32, ActivityTracker<MemOp>>,
+    /// 所有DRAM通道汇总的读写统计
+    pub dram_activity: ActivityTracker<MemOp>,
+
+    /// 每个阶段的周期数和硬件操作计数，用于能耗估算和 roofline 分析
+    pub stages: BTreeMap<RunStage, HwCounts>,
+    /// 每种算子（tile 的 `optype`）的周期数和硬件操作计数
+    pub operations: BTreeMap<String, HwCounts>,
+
+    // the runtime info
+    pub current_stage: RunStage,
+
+    /// 事件流，逐条写入 `events.jsonl`，`counts.json` 中只保留摘要
+    pub events: EventLog,
+    /// NPU 和 PIM 的重叠分析，在事件被过滤之前更新
+    #[serde(skip)]
+    pub overlap: OverlapTracker,
+    /// 输出文件所在的目录
+    #[serde(skip)]
+    pub out_dir: PathBuf,
+    /// busy/idle 时间间隔统计的分桶方式
+    #[serde(skip)]
+    pub histogram: HistogramStrategy,
+    /// 每隔固定周期对计数器采样，写入 `timeseries.csv`
+    #[serde(skip)]
+    pub sampler: Sampler,
 }
-impl Default for MemStatus {
+
+impl Default for GlobalCountsCtx {
     fn default() -> Self {
-        MemStatus::Idle(0)
+        Self::with_histogram(HistogramStrategy::default())
     }
 }
 
-#[derive(Default, Serialize, PartialEq, Eq, PartialOrd, Ord, AddAssign)]
-pub struct Cycle(u64);
-
-/// record the current ongoing operations
-#[derive(Default, Serialize)]
-pub struct Counts {
-    pub loads: u64,
-    pub stores: u64,
-    pub computes: u64,
+/// 创建一个登记了所有 [`MemOp`] 的 tracker
+fn mem_op_tracker(histogram: HistogramStrategy) -> ActivityTracker<MemOp> {
+    let mut tracker = ActivityTracker::with_histogram(histogram);
+    tracker.register(MemOp::Compute);
+    tracker.register_group(MemOp::LoadOrStore, &[MemOp::Load, MemOp::Store]);
+    tracker
 }
 
-#[derive(Default, Serialize)]
-pub struct OpCycles {
-    pub loads: Cycle,
-    pub stores: Cycle,
-    pub computes: Cycle,
-    pub load_or_stores: Cycle,
-}
+impl GlobalCountsCtx {
+    /// 使用 `histogram` 统计 busy/idle 时间间隔
+    pub fn with_histogram(histogram: HistogramStrategy) -> Self {
+        Self {
+            last_cycle: 0,
+            activity: mem_op_tracker(histogram),
+            cores: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            dram_activity: mem_op_tracker(histogram),
+            stages: BTreeMap::new(),
+            operations: BTreeMap::new(),
+            current_stage: RunStage::default(),
+            events: EventLog::default(),
+            overlap: OverlapTracker::default(),
+            out_dir: PathBuf::from("."),
+            histogram,
+            sampler: Sampler::default(),
+        }
+    }
 
-#[derive(Default, Serialize)]
-pub struct CurrentStatus {
-    pub loads: MemStatus,
-    pub stores: MemStatus,
-    pub computes: MemStatus,
-    pub load_or_stores: MemStatus,
-}
+    /// 所有的 tracker，以及它所属的范围和单元编号
+    pub fn trackers(&self) -> Vec<(&'static str, Option<u32>, &ActivityTracker<MemOp>)> {
+        let mut trackers = vec![("all", None, &self.activity)];
+        trackers.extend(self.cores.iter().map(|(&core, t)| ("core", Some(core), t)));
+        trackers.push(("dram", None, &self.dram_activity));
+        trackers.extend(
+            self.channels
+                .iter()
+                .map(|(&ch, t)| ("channel", Some(ch), t)),
+        );
+        trackers
+    }
 
-#[derive(Default, Serialize)]
-pub struct CycleHistogram {
-    pub loads: BTreeMap<Cycle, u64>,
-    pub stores: BTreeMap<Cycle, u64>,
-    pub computes: BTreeMap<Cycle, u64>,
-    pub load_or_stores: BTreeMap<Cycle, u64>,
-}
+    /// 记录一个阶段或 NPU/PIM 的事件，重叠分析总能看到它，无论事件流是否将其过滤
+    fn push_event(&mut self, event: EventType, stage: RunStage, cycle: u64) {
+        let event = Event {
+            cycle,
+            stage,
+            event,
+        };
+        self.overlap.push(&event);
+        self.events.push(event);
+    }
 
-#[derive(Default, Serialize)]
-pub struct GlobalCountsCtx {
-    // the statistics
-    /// the last cycle
-    pub last_cycle: u64,
+    /// 开始 `n` 个 `op` 操作，记录因此变为 busy 的操作的事件
+    pub fn add_ops(&mut self, op: MemOp, n: u64, cycle: u64) {
+        let stage = self.current_stage;
+        let events = &mut self.events;
+        self.activity.add(op, n, cycle, &mut |op, transition| {
+            events.push(Event::transition(cycle, stage, op, transition));
+        });
+    }
+
+    /// 结束 `n` 个 `op` 操作，记录因此变为 idle 的操作的事件
+    ///
+    /// 如果会导致计数变为负值，不做任何修改并返回`false`
+    pub fn reduce_ops(&mut self, op: MemOp, n: u64, cycle: u64) -> bool {
+        let stage = self.current_stage;
+        let events = &mut self.events;
+        let reduced = self.activity.reduce(op, n, cycle, &mut |op, transition| {
+            events.push(Event::transition(cycle, stage, op, transition));
+        });
+        if !reduced {
+            error!("错误：尝试将{op:?}的计数减少到负值");
+        }
+        reduced
+    }
 
-    /// 总的busy 的时间
-    pub busy_cycles: OpCycles,
-    /// 持续idle时间
-    pub idle_cycles: OpCycles,
+    /// 登记 `num_cores` 个核心和 `num_channels` 个通道，使没有任何操作的单元也出现在统计中
+    pub fn register_units(&mut self, num_cores: u32, num_channels: u32) {
+        let histogram = self.histogram;
+        for core in 0..num_cores {
+            self.cores
+                .entry(core)
+                .or_insert_with(|| mem_op_tracker(histogram));
+        }
+        for channel in 0..num_channels {
+            self.channels
+                .entry(channel)
+                .or_insert_with(|| mem_op_tracker(histogram));
+        }
+    }
 
-    /// idle 的时间间隔统计
-    pub idle_histo: CycleHistogram,
-    /// busy 的时间间隔统计
-    pub busy_histo: CycleHistogram,
+    /// 在核心 `core` 上开始 `n` 个 `op` 操作，同时计入汇总统计
+    pub fn add_core_ops(&mut self, core: u32, op: MemOp, n: u64, cycle: u64) {
+        if op.is_group() {
+            error!("错误：{op:?}不能直接增加");
+            return;
+        }
+        let histogram = self.histogram;
+        let tracker = self
+            .cores
+            .entry(core)
+            .or_insert_with(|| mem_op_tracker(histogram));
+        tracker.add(op, n, cycle, &mut |_, _| {});
+        self.add_ops(op, n, cycle);
+    }
 
-    /// 累计的操作次数
-    pub all_counts: Counts,
+    /// 在核心 `core` 上结束 `n` 个 `op` 操作，同时计入汇总统计
+    ///
+    /// 如果会导致该核心的计数变为负值，不做任何修改并返回`false`
+    pub fn reduce_core_ops(&mut self, core: u32, op: MemOp, n: u64, cycle: u64) -> bool {
+        let histogram = self.histogram;
+        let tracker = self
+            .cores
+            .entry(core)
+            .or_insert_with(|| mem_op_tracker(histogram));
+        if op.is_group() || !tracker.reduce(op, n, cycle, &mut |_, _| {}) {
+            error!("错误：尝试将核心{core}的{op:?}的计数减少到负值");
+            return false;
+        }
+        self.reduce_ops(op, n, cycle)
+    }
 
-    // the runtime info
-    /// current load store and computes operations
-    pub current_counts: Counts,
-    pub current_stage: RunStage,
-    // 记录上一次加载、存储、计算操作的开始时间
-    pub current_status: CurrentStatus,
+    /// 在通道 `channel` 上开始 `n` 个 `op` 操作，同时计入DRAM汇总统计
+    pub fn add_channel_ops(&mut self, channel: u32, op: MemOp, n: u64, cycle: u64) {
+        if op.is_group() {
+            error!("错误：{op:?}不能直接增加");
+            return;
+        }
+        let histogram = self.histogram;
+        let tracker = self
+            .channels
+            .entry(channel)
+            .or_insert_with(|| mem_op_tracker(histogram));
+        tracker.add(op, n, cycle, &mut |_, _| {});
+        self.dram_activity.add(op, n, cycle, &mut |_, _| {});
+    }
 
-    pub event_vec: Vec<Event>,
+    /// 在通道 `channel` 上结束 `n` 个 `op` 操作，同时计入DRAM汇总统计
+    ///
+    /// 如果会导致该通道的计数变为负值，不做任何修改并返回`false`
+    pub fn reduce_channel_ops(&mut self, channel: u32, op: MemOp, n: u64, cycle: u64) -> bool {
+        let histogram = self.histogram;
+        let tracker = self
+            .channels
+            .entry(channel)
+            .or_insert_with(|| mem_op_tracker(histogram));
+        if op.is_group() || !tracker.reduce(op, n, cycle, &mut |_, _| {}) {
+            error!("错误：尝试将通道{channel}的{op:?}的计数减少到负值");
+            return false;
+        }
+        self.dram_activity.reduce(op, n, cycle, &mut |_, _| {})
+    }
 }
 
 #[no_mangle]
 pub extern "C" fn update_last_cycle(ctx: &mut GlobalCountsCtx, cycle: u64) {
+    let stage = ctx.stages.entry(ctx.current_stage).or_default();
+    stage.cycles += cycle.saturating_sub(ctx.last_cycle);
     ctx.last_cycle = cycle;
+    if ctx.sampler.is_due(cycle) {
+        let mut sampler = std::mem::take(&mut ctx.sampler);
+        sampler.sample(cycle, ctx);
+        ctx.sampler = sampler;
+    }
 }
 
-/// 创建一个新的`GlobalCountsCtx`。
+/// 创建一个新的`GlobalCountsCtx`，事件流写入输出目录下的 `events.jsonl`。
+///
+/// 输出目录为 `out_dir`，为空时使用配置的 `log_dir`，都为空时使用当前目录。需要在设置和配置
+/// 初始化之后调用，以使用其中的事件过滤条件、采样间隔和 `log_dir`。
+///
+/// # 返回值
+///
+/// 无法创建输出目录或事件流文件时记录错误并返回空指针
 #[no_mangle]
-pub extern "C" fn new_global_counts_ctx() -> *mut GlobalCountsCtx {
+pub extern "C" fn new_global_counts_ctx(out_dir: *const c_char) -> *mut GlobalCountsCtx {
     info!("创建新的GlobalCountsCtx");
-    Box::into_raw(Box::new(GlobalCountsCtx::default()))
+    let out_dir = output_dir(c_path(out_dir));
+    let events_path = out_dir.join("events.jsonl");
+    let events = match std::fs::create_dir_all(&out_dir)
+        .and_then(|()| EventLog::to_file(&events_path, current_event_filter()))
+    {
+        Ok(events) => events,
+        Err(err) => {
+            error!("无法创建{}: {err}", events_path.display());
+            return std::ptr::null_mut();
+        }
+    };
+    Box::into_raw(Box::new(GlobalCountsCtx {
+        events,
+        out_dir,
+        sampler: Sampler::new(current_sampling().interval),
+        ..GlobalCountsCtx::with_histogram(current_histogram_strategy())
+    }))
 }
 
 /// 释放`GlobalCountsCtx`。
@@ -104,7 +267,19 @@ pub extern "C" fn drop_global_counts_ctx(ctx: *mut GlobalCountsCtx) {
 
 /// Run stages
 #[repr(C)]
-#[derive(Debug, Serialize, Default, Clone, Copy)]
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
 pub enum RunStage {
     #[default]
     A,
@@ -116,21 +291,84 @@ pub enum RunStage {
     Finished,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Event {
-    cycle: u64,
-    stage: RunStage,
-    event: EventType,
+    pub cycle: u64,
+    pub stage: RunStage,
+    pub event: EventType,
 }
-#[derive(Debug, Serialize, Clone)]
+impl Event {
+    fn transition(cycle: u64, stage: RunStage, op: MemOp, transition: Transition) -> Self {
+        let event = match transition {
+            Transition::BusyStart => EventType::MemEventStart(op),
+            Transition::BusyEnd => EventType::MemEventEnd(op),
+        };
+        Event {
+            cycle,
+            stage,
+            event,
+        }
+    }
+}
+
+/// The resources tracked by [`GlobalCountsCtx::activity`].
+#[repr(C)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MemOp {
     Load,
     Store,
     Compute,
+    /// Busy while a load or a store is ongoing.
     LoadOrStore,
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl MemOp {
+    /// Whether the resource is a union group, which only changes through its members.
+    pub fn is_group(self) -> bool {
+        matches!(self, MemOp::LoadOrStore)
+    }
+}
+
+/// Hardware operations counted per stage.
+#[repr(C)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HwOp {
+    /// A DRAM read request of `dram_req_size` bytes.
+    DramRead,
+    /// A DRAM write request of `dram_req_size` bytes, including PIM global buffer writes.
+    DramWrite,
+    /// A PIM header, which opens a row in every bank of the channel.
+    PimHeader,
+    /// A PIM COMP command, `pim_comp_coverage` MACs in every bank of the channel.
+    PimComp,
+    /// A transfer of `dram_req_size` bytes into or out of a scratchpad.
+    SramAccess,
+    /// A multiply-accumulate of the systolic array.
+    SystolicMac,
+    /// A packet through the interconnect.
+    IcntFlit,
+}
+
+/// The hardware operations issued during a stage or by an operation, and the cycles it took.
+///
+/// For an operation, `cycles` sums the core cycles of its tiles from dispatch to completion.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HwCounts {
+    pub cycles: u64,
+    pub ops: BTreeMap<HwOp, u64>,
+}
+
+impl HwCounts {
+    pub fn get(&self, op: HwOp) -> u64 {
+        self.ops.get(&op).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, op: HwOp, n: u64) {
+        *self.ops.entry(op).or_default() += n;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum EventType {
     MemEventStart(MemOp),
     MemEventEnd(MemOp),
@@ -144,48 +382,35 @@ pub enum EventType {
 
 #[no_mangle]
 pub extern "C" fn update_stage(ctx: &mut GlobalCountsCtx, stage: RunStage, cycle: u64) {
-    ctx.event_vec.push(Event {
-        cycle,
-        stage,
-        event: EventType::StageStart,
-    });
+    ctx.push_event(EventType::StageStart, stage, cycle);
     ctx.current_stage = stage;
 }
 
 #[no_mangle]
 pub extern "C" fn end_stage(ctx: &mut GlobalCountsCtx, stage: RunStage, cycle: u64) {
-    ctx.event_vec.push(Event {
-        cycle,
-        stage,
-        event: EventType::StageEnd,
-    });
+    ctx.push_event(EventType::StageEnd, stage, cycle);
 }
 
 #[no_mangle]
-pub extern "C" fn npu_finished(ctx: &mut GlobalCountsCtx, cycle: u64) {
-    ctx.event_vec.push(Event {
-        cycle,
-        stage: ctx.current_stage,
-        event: EventType::NpuFinished,
-    });
+pub extern "C" fn npu_start(ctx: &mut GlobalCountsCtx, cycle: u64) {
+    ctx.push_event(EventType::NpuStart, ctx.current_stage, cycle);
 }
 
 #[no_mangle]
-pub extern "C" fn pim_finished(ctx: &mut GlobalCountsCtx, cycle: u64) {
-    ctx.event_vec.push(Event {
-        cycle,
-        stage: ctx.current_stage,
-        event: EventType::PimFinished,
-    });
+pub extern "C" fn pim_start(ctx: &mut GlobalCountsCtx, cycle: u64) {
+    ctx.push_event(EventType::PimStart, ctx.current_stage, cycle);
 }
 
-/// 保存累计的数据到文件
 #[no_mangle]
-#[allow(static_mut_refs)]
-pub extern "C" fn save_global_counts_to_file(ctx: &GlobalCountsCtx) {
-    let file = File::create("counts.json").expect("无法创建文件");
-    serde_json::to_writer_pretty(file, &ctx).expect("无法写入文件");
+pub extern "C" fn npu_finished(ctx: &mut GlobalCountsCtx, cycle: u64) {
+    ctx.push_event(EventType::NpuFinished, ctx.current_stage, cycle);
+}
+
+#[no_mangle]
+pub extern "C" fn pim_finished(ctx: &mut GlobalCountsCtx, cycle: u64) {
+    ctx.push_event(EventType::PimFinished, ctx.current_stage, cycle);
 }
+
 /// 增加加载操作的计数
 ///
 /// # 参数
@@ -194,53 +419,7 @@ pub extern "C" fn save_global_counts_to_file(ctx: &GlobalCountsCtx) {
 #[no_mangle]
 #[allow(static_mut_refs)]
 pub extern "C" fn add_loads(ctx: &mut GlobalCountsCtx, loads: u64, cycle: u64) {
-    ctx.current_counts.loads += loads;
-    ctx.all_counts.loads += loads;
-
-    if ctx.current_counts.loads == loads {
-        match ctx.current_status.loads {
-            MemStatus::Idle(start_cycle) => {
-                let idle_duration = cycle - start_cycle;
-                if idle_duration != 0 {
-                    *ctx.idle_histo
-                        .loads
-                        .entry(Cycle(idle_duration))
-                        .or_default() += 1;
-
-                    ctx.idle_cycles.loads += Cycle(idle_duration);
-                }
-                ctx.current_status.loads = MemStatus::Busy(cycle);
-
-                ctx.event_vec.push(Event {
-                    cycle,
-                    stage: ctx.current_stage,
-                    event: EventType::MemEventStart(MemOp::Load),
-                });
-
-                match ctx.current_status.load_or_stores {
-                    MemStatus::Idle(start_cycle) => {
-                        let idle_duration = cycle - start_cycle;
-                        if idle_duration != 0 {
-                            *ctx.idle_histo
-                                .load_or_stores
-                                .entry(Cycle(idle_duration))
-                                .or_default() += 1;
-                            ctx.idle_cycles.load_or_stores += Cycle(idle_duration);
-                        }
-                        ctx.current_status.load_or_stores = MemStatus::Busy(cycle);
-                        ctx.event_vec.push(Event {
-                            cycle,
-                            stage: ctx.current_stage,
-                            event: EventType::MemEventStart(MemOp::LoadOrStore),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-    // 添加loads可能会从idle变成busy
+    ctx.add_ops(MemOp::Load, loads, cycle);
 }
 
 /// 增加存储操作的计数
@@ -250,50 +429,7 @@ pub extern "C" fn add_loads(ctx: &mut GlobalCountsCtx, loads: u64, cycle: u64) {
 /// * `stores` - 要增加的存储操作数量
 #[no_mangle]
 pub extern "C" fn add_stores(ctx: &mut GlobalCountsCtx, stores: u64, cycle: u64) {
-    ctx.current_counts.stores += stores;
-    ctx.all_counts.stores += stores;
-
-    if ctx.current_counts.stores == stores {
-        match ctx.current_status.stores {
-            MemStatus::Idle(start_cycle) => {
-                let idle_duration = cycle - start_cycle;
-                if idle_duration != 0 {
-                    *ctx.idle_histo
-                        .stores
-                        .entry(Cycle(idle_duration))
-                        .or_default() += 1;
-                    ctx.idle_cycles.stores += Cycle(idle_duration);
-                }
-                ctx.current_status.stores = MemStatus::Busy(cycle);
-                ctx.event_vec.push(Event {
-                    cycle,
-                    stage: ctx.current_stage,
-                    event: EventType::MemEventStart(MemOp::Store),
-                });
-
-                match ctx.current_status.load_or_stores {
-                    MemStatus::Idle(start_cycle) => {
-                        let idle_duration = cycle - start_cycle;
-                        if idle_duration != 0 {
-                            *ctx.idle_histo
-                                .load_or_stores
-                                .entry(Cycle(idle_duration))
-                                .or_default() += 1;
-                            ctx.idle_cycles.load_or_stores += Cycle(idle_duration);
-                        }
-                        ctx.current_status.load_or_stores = MemStatus::Busy(cycle);
-                        ctx.event_vec.push(Event {
-                            cycle,
-                            stage: ctx.current_stage,
-                            event: EventType::MemEventStart(MemOp::LoadOrStore),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
+    ctx.add_ops(MemOp::Store, stores, cycle);
 }
 
 /// 增加计算操作的计数
@@ -301,10 +437,10 @@ pub extern "C" fn add_stores(ctx: &mut GlobalCountsCtx, stores: u64, cycle: u64)
 /// # 参数
 ///
 /// * `computes` - 要增加的计算操作数量
+/// * `cycle` - 当前周期，计算单元从空闲变为忙碌时记录
 #[no_mangle]
-pub extern "C" fn add_computes(ctx: &mut GlobalCountsCtx, computes: u64) {
-    ctx.current_counts.computes += computes;
-    ctx.all_counts.computes += computes;
+pub extern "C" fn add_computes(ctx: &mut GlobalCountsCtx, computes: u64, cycle: u64) {
+    ctx.add_ops(MemOp::Compute, computes, cycle);
 }
 
 /// 获取当前的加载操作计数
@@ -314,7 +450,7 @@ pub extern "C" fn add_computes(ctx: &mut GlobalCountsCtx, computes: u64) {
 /// 返回当前的加载操作总数
 #[no_mangle]
 pub extern "C" fn get_loads(ctx: &GlobalCountsCtx) -> u64 {
-    ctx.current_counts.loads
+    ctx.activity.current(MemOp::Load)
 }
 
 /// 获取当前的存储操作计数
@@ -324,7 +460,7 @@ pub extern "C" fn get_loads(ctx: &GlobalCountsCtx) -> u64 {
 /// 返回当前的存储操作总数
 #[no_mangle]
 pub extern "C" fn get_stores(ctx: &GlobalCountsCtx) -> u64 {
-    ctx.current_counts.stores
+    ctx.activity.current(MemOp::Store)
 }
 
 /// 获取当前的计算操作计数
@@ -334,7 +470,180 @@ pub extern "C" fn get_stores(ctx: &GlobalCountsCtx) -> u64 {
 /// 返回当前的计算操作总数
 #[no_mangle]
 pub extern "C" fn get_computes(ctx: &GlobalCountsCtx) -> u64 {
-    ctx.current_counts.computes
+    ctx.activity.current(MemOp::Compute)
+}
+
+/// 一个阶段的周期数、占整个运行的比例和硬件操作计数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageBreakdown {
+    pub stage: RunStage,
+    pub share: f64,
+    pub counts: HwCounts,
+}
+
+impl GlobalCountsCtx {
+    /// 所有核心汇总的 `op` 处于 busy 状态的时间占比
+    pub fn busy_ratio(&self, op: MemOp) -> Option<f64> {
+        self.activity.get(op)?.busy_ratio()
+    }
+
+    /// 周期在 `start..end` 内的事件
+    pub fn events_in_window(&self, start: u64, end: u64) -> std::io::Result<Vec<Event>> {
+        let mut events = self.events.events()?;
+        events.retain(|event| (start..end).contains(&event.cycle));
+        Ok(events)
+    }
+
+    /// 每个阶段的周期数、占总周期的比例和硬件操作计数
+    pub fn stage_breakdown(&self) -> Vec<StageBreakdown> {
+        let total: u64 = self.stages.values().map(|counts| counts.cycles).sum();
+        self.stages
+            .iter()
+            .map(|(&stage, counts)| StageBreakdown {
+                stage,
+                share: if total == 0 {
+                    0.0
+                } else {
+                    counts.cycles as f64 / total as f64
+                },
+                counts: counts.clone(),
+            })
+            .collect()
+    }
+
+    /// 算子 `optype` 的计数，第一次使用时创建
+    fn operation_mut(&mut self, optype: &str) -> &mut HwCounts {
+        if !self.operations.contains_key(optype) {
+            self.operations
+                .insert(optype.to_string(), HwCounts::default());
+        }
+        self.operations.get_mut(optype).unwrap()
+    }
+}
+
+/// 将 C 字符串转换为 `&str`，空指针返回 `None`
+fn c_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { std::ffi::CStr::from_ptr(s) }.to_str().ok()
+}
+
+/// 在当前阶段增加 `n` 个硬件操作 `op` 的计数
+///
+/// # 参数
+///
+/// * `optype` - 产生这些操作的算子类型，为空时只计入阶段
+#[no_mangle]
+pub extern "C" fn add_hw_ops(ctx: &mut GlobalCountsCtx, op: HwOp, n: u64, optype: *const c_char) {
+    ctx.stages.entry(ctx.current_stage).or_default().add(op, n);
+    if let Some(optype) = c_str(optype) {
+        ctx.operation_mut(optype).add(op, n);
+    }
+}
+
+/// 增加算子 `optype` 的一个 tile 从分派到完成的周期数
+#[no_mangle]
+pub extern "C" fn add_operation_cycles(
+    ctx: &mut GlobalCountsCtx,
+    optype: *const c_char,
+    cycles: u64,
+) {
+    if let Some(optype) = c_str(optype) {
+        ctx.operation_mut(optype).cycles += cycles;
+    }
+}
+
+/// 登记核心和DRAM通道的数量，使没有任何操作的单元也出现在统计中
+#[no_mangle]
+pub extern "C" fn register_units(ctx: &mut GlobalCountsCtx, num_cores: u32, num_channels: u32) {
+    ctx.register_units(num_cores, num_channels);
+}
+
+/// 增加核心 `core` 上的操作计数，同时计入汇总统计
+///
+/// # 参数
+///
+/// * `core` - 核心编号
+/// * `op` - 操作类型，不能是`LoadOrStore`
+/// * `n` - 要增加的操作数量
+/// * `cycle` - 当前的核心周期
+#[no_mangle]
+pub extern "C" fn add_core_ops(
+    ctx: &mut GlobalCountsCtx,
+    core: u32,
+    op: MemOp,
+    n: u64,
+    cycle: u64,
+) {
+    ctx.add_core_ops(core, op, n, cycle);
+}
+
+/// 减少核心 `core` 上的操作计数，同时计入汇总统计
+///
+/// # 返回值
+///
+/// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
+#[no_mangle]
+pub extern "C" fn reduce_core_ops(
+    ctx: &mut GlobalCountsCtx,
+    core: u32,
+    op: MemOp,
+    n: u64,
+    cycle: u64,
+) -> bool {
+    ctx.reduce_core_ops(core, op, n, cycle)
+}
+
+/// 获取核心 `core` 上当前的操作计数
+#[no_mangle]
+pub extern "C" fn get_core_ops(ctx: &GlobalCountsCtx, core: u32, op: MemOp) -> u64 {
+    ctx.cores
+        .get(&core)
+        .map_or(0, |tracker| tracker.current(op))
+}
+
+/// 增加DRAM通道 `channel` 上的读写计数，同时计入DRAM汇总统计
+///
+/// # 参数
+///
+/// * `channel` - 通道编号
+/// * `op` - `Load`或`Store`
+/// * `n` - 要增加的请求数量
+/// * `cycle` - 当前的DRAM周期
+#[no_mangle]
+pub extern "C" fn add_channel_ops(
+    ctx: &mut GlobalCountsCtx,
+    channel: u32,
+    op: MemOp,
+    n: u64,
+    cycle: u64,
+) {
+    ctx.add_channel_ops(channel, op, n, cycle);
+}
+
+/// 减少DRAM通道 `channel` 上的读写计数，同时计入DRAM汇总统计
+///
+/// # 返回值
+///
+/// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
+#[no_mangle]
+pub extern "C" fn reduce_channel_ops(
+    ctx: &mut GlobalCountsCtx,
+    channel: u32,
+    op: MemOp,
+    n: u64,
+    cycle: u64,
+) -> bool {
+    ctx.reduce_channel_ops(channel, op, n, cycle)
+}
+
+/// 获取DRAM通道 `channel` 上当前的读写计数
+#[no_mangle]
+pub extern "C" fn get_channel_ops(ctx: &GlobalCountsCtx, channel: u32, op: MemOp) -> u64 {
+    ctx.channels
+        .get(&channel)
+        .map_or(0, |tracker| tracker.current(op))
 }
 
 /// 减少加载操作的计数
@@ -349,54 +658,7 @@ pub extern "C" fn get_computes(ctx: &GlobalCountsCtx) -> u64 {
 #[no_mangle]
 #[allow(static_mut_refs)]
 pub extern "C" fn reduce_loads(ctx: &mut GlobalCountsCtx, loads: u64, cycle: u64) -> bool {
-    if ctx.current_counts.loads < loads {
-        error!("错误：尝试将GLOBAL_LOADS减少到负值");
-        return false;
-    }
-    ctx.current_counts.loads -= loads;
-    // check triggers
-    if ctx.current_counts.loads == 0 {
-        match ctx.current_status.loads {
-            MemStatus::Busy(start_cycle) => {
-                let busy_duration = cycle - start_cycle;
-                *ctx.busy_histo
-                    .loads
-                    .entry(Cycle(busy_duration))
-                    .or_default() += 1;
-                ctx.busy_cycles.loads += Cycle(busy_duration);
-                ctx.current_status.loads = MemStatus::Idle(cycle);
-                ctx.event_vec.push(Event {
-                    cycle: cycle,
-                    stage: ctx.current_stage,
-                    event: EventType::MemEventEnd(MemOp::Load),
-                });
-
-                match (
-                    &ctx.current_status.load_or_stores,
-                    &ctx.current_status.stores,
-                ) {
-                    (MemStatus::Busy(start_cycle), MemStatus::Idle(_)) => {
-                        let busy_duration = cycle - start_cycle;
-                        *ctx.busy_histo
-                            .load_or_stores
-                            .entry(Cycle(busy_duration))
-                            .or_default() += 1;
-                        ctx.busy_cycles.load_or_stores += Cycle(busy_duration);
-                        ctx.current_status.load_or_stores = MemStatus::Idle(cycle);
-                        ctx.event_vec.push(Event {
-                            cycle: cycle,
-                            stage: ctx.current_stage,
-                            event: EventType::MemEventEnd(MemOp::LoadOrStore),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-
-    true
+    ctx.reduce_ops(MemOp::Load, loads, cycle)
 }
 
 /// 减少存储操作的计数
@@ -410,54 +672,7 @@ pub extern "C" fn reduce_loads(ctx: &mut GlobalCountsCtx, loads: u64, cycle: u64
 /// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
 #[no_mangle]
 pub extern "C" fn reduce_stores(ctx: &mut GlobalCountsCtx, stores: u64, cycle: u64) -> bool {
-    if ctx.current_counts.stores < stores {
-        error!("错误：尝试将GLOBAL_STORES减少到负值");
-        return false;
-    }
-    ctx.current_counts.stores -= stores;
-
-    if ctx.current_counts.stores == 0 {
-        match ctx.current_status.stores {
-            MemStatus::Busy(start_cycle) => {
-                let busy_duration = cycle - start_cycle;
-                *ctx.busy_histo
-                    .stores
-                    .entry(Cycle(busy_duration))
-                    .or_default() += 1;
-                ctx.busy_cycles.stores += Cycle(busy_duration);
-                ctx.current_status.stores = MemStatus::Idle(cycle);
-                ctx.event_vec.push(Event {
-                    cycle: cycle,
-                    stage: ctx.current_stage,
-                    event: EventType::MemEventEnd(MemOp::Store),
-                });
-
-                match (
-                    &ctx.current_status.load_or_stores,
-                    &ctx.current_status.loads,
-                ) {
-                    (MemStatus::Busy(start_cycle), MemStatus::Idle(_)) => {
-                        let busy_duration = cycle - start_cycle;
-                        *ctx.busy_histo
-                            .load_or_stores
-                            .entry(Cycle(busy_duration))
-                            .or_default() += 1;
-                        ctx.busy_cycles.load_or_stores += Cycle(busy_duration);
-                        ctx.current_status.load_or_stores = MemStatus::Idle(cycle);
-                        ctx.event_vec.push(Event {
-                            cycle: cycle,
-                            stage: ctx.current_stage,
-                            event: EventType::MemEventEnd(MemOp::LoadOrStore),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-
-    true
+    ctx.reduce_ops(MemOp::Store, stores, cycle)
 }
 
 /// 减少计算操作的计数
@@ -465,22 +680,20 @@ pub extern "C" fn reduce_stores(ctx: &mut GlobalCountsCtx, stores: u64, cycle: u
 /// # 参数
 ///
 /// * `computes` - 要减少的计算操作数量
+/// * `cycle` - 当前周期，计算单元从忙碌变为空闲时记录
 ///
 /// # 返回值
 ///
 /// 如果减少操作成功，返回`true`；如果减少操作会导致计数变为负值，返回`false`
 #[no_mangle]
-pub extern "C" fn reduce_computes(ctx: &mut GlobalCountsCtx, computes: u64) -> bool {
-    if ctx.current_counts.computes < computes {
-        error!("错误：尝试将GLOBAL_COMPUTES减少到负值");
-        return false;
-    }
-    ctx.current_counts.computes -= computes;
-    true
+pub extern "C" fn reduce_computes(ctx: &mut GlobalCountsCtx, computes: u64, cycle: u64) -> bool {
+    ctx.reduce_ops(MemOp::Compute, computes, cycle)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_save() {
         // let file = File::create("counts_test.json").expect("无法创建文件");
@@ -497,4 +710,80 @@ mod tests {
         // update_global_on_cycle(&mut global_count, 6);
         // serde_json::to_writer_pretty(file, &global_count).expect("无法写入文件");
     }
+
+    #[test]
+    fn test_compute_busy_idle() {
+        let mut ctx = GlobalCountsCtx::default();
+        add_computes(&mut ctx, 2, 10);
+        add_computes(&mut ctx, 1, 12);
+        assert!(reduce_computes(&mut ctx, 2, 15));
+        assert!(reduce_computes(&mut ctx, 1, 20));
+        add_computes(&mut ctx, 1, 25);
+        assert!(reduce_computes(&mut ctx, 1, 30));
+        assert!(!reduce_computes(&mut ctx, 1, 31));
+
+        let computes = ctx.activity.get(MemOp::Compute).unwrap();
+        assert_eq!(computes.busy_cycles, Cycle(15));
+        assert_eq!(computes.idle_cycles, Cycle(15));
+        assert_eq!(computes.busy_histo.buckets()[&Cycle(10)], 1);
+        assert_eq!(computes.busy_histo.buckets()[&Cycle(5)], 1);
+        assert_eq!(computes.total, 4);
+        assert_eq!(computes.status, MemStatus::Idle(30));
+        // 计算不属于内存访问
+        let load_or_stores = ctx.activity.get(MemOp::LoadOrStore).unwrap();
+        assert_eq!(load_or_stores.busy_cycles, Cycle(0));
+        assert_eq!(ctx.events.len(), 4);
+    }
+
+    #[test]
+    fn test_load_or_stores() {
+        let mut ctx = GlobalCountsCtx::default();
+        add_loads(&mut ctx, 1, 10);
+        add_stores(&mut ctx, 1, 12);
+        assert!(reduce_loads(&mut ctx, 1, 15));
+        assert!(reduce_stores(&mut ctx, 1, 20));
+        assert!(!reduce_stores(&mut ctx, 1, 21));
+
+        let load_or_stores = ctx.activity.get(MemOp::LoadOrStore).unwrap();
+        assert_eq!(load_or_stores.busy_cycles, Cycle(10));
+        assert_eq!(load_or_stores.idle_cycles, Cycle(10));
+        // load、store 各开始、结束一次，load_or_stores 也只开始、结束一次
+        assert_eq!(ctx.events.len(), 6);
+    }
+
+    #[test]
+    fn test_per_unit_counts() {
+        let mut ctx = GlobalCountsCtx::default();
+        ctx.register_units(2, 2);
+        add_core_ops(&mut ctx, 0, MemOp::Load, 1, 10);
+        add_core_ops(&mut ctx, 1, MemOp::Load, 2, 15);
+        assert!(reduce_core_ops(&mut ctx, 0, MemOp::Load, 1, 20));
+        assert!(!reduce_core_ops(&mut ctx, 0, MemOp::Load, 1, 21));
+        assert!(reduce_core_ops(&mut ctx, 1, MemOp::Load, 2, 30));
+
+        assert_eq!(
+            ctx.cores[&0].get(MemOp::Load).unwrap().busy_cycles,
+            Cycle(10)
+        );
+        assert_eq!(
+            ctx.cores[&1].get(MemOp::Load).unwrap().busy_cycles,
+            Cycle(15)
+        );
+        let loads = ctx.activity.get(MemOp::Load).unwrap();
+        assert_eq!(loads.busy_cycles, Cycle(20));
+        assert_eq!(loads.total, 3);
+
+        add_channel_ops(&mut ctx, 1, MemOp::Store, 1, 5);
+        assert_eq!(get_channel_ops(&ctx, 1, MemOp::Store), 1);
+        assert!(reduce_channel_ops(&mut ctx, 1, MemOp::Store, 1, 9));
+        assert_eq!(
+            ctx.channels[&1].get(MemOp::Store).unwrap().busy_cycles,
+            Cycle(4)
+        );
+        assert_eq!(ctx.channels[&0].get(MemOp::Store).unwrap().total, 0);
+        let dram = ctx.dram_activity.get(MemOp::LoadOrStore).unwrap();
+        assert_eq!(dram.busy_cycles, Cycle(4));
+        // 通道的读写不计入核心的汇总统计
+        assert_eq!(ctx.activity.get(MemOp::Store).unwrap().total, 0);
+    }
 }
@@ -0,0 +1,239 @@
+//! Model configs imported from HuggingFace `config.json` files.
+//!
+//! A model config file that looks like a HuggingFace config (it has `model_type` or
+//! `architectures`) is translated into the `model_*` fields of [`SimulationConfig`] when it is
+//! merged, so `--model_config path/to/Llama-3-8B/config.json` works directly. Architectural
+//! features the simulator does not model, such as sliding-window attention or mixture of experts,
+//! are reported as warnings; the rest of the model is still imported.
+//!
+//! [`SimulationConfig`]: crate::global_config::SimulationConfig
+
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::{
+    config_loader::ConfigError,
+    global_config::{Activation, SimulationConfig},
+    registry::Registered,
+};
+
+/// The subset of a HuggingFace config that maps onto [`SimulationConfig`], with the GPT-2 and OPT
+/// spellings of the same keys.
+#[derive(Debug, Deserialize)]
+struct HfConfig {
+    #[serde(rename = "_name_or_path")]
+    name_or_path: Option<String>,
+    #[serde(alias = "n_embd")]
+    hidden_size: u32,
+    #[serde(alias = "n_head")]
+    num_attention_heads: u32,
+    num_key_value_heads: Option<u32>,
+    #[serde(alias = "n_layer")]
+    num_hidden_layers: u32,
+    #[serde(alias = "ffn_dim", alias = "n_inner")]
+    intermediate_size: Option<u32>,
+    vocab_size: u32,
+    #[serde(alias = "n_positions")]
+    max_position_embeddings: Option<u32>,
+    #[serde(alias = "activation_function")]
+    hidden_act: Option<String>,
+    #[serde(default = "default_tie_word_embeddings")]
+    tie_word_embeddings: bool,
+    /// Everything else, checked for unsupported features.
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+/// `transformers` ties the embeddings unless a config says otherwise.
+fn default_tie_word_embeddings() -> bool {
+    true
+}
+
+/// Keys that change the architecture in a way the simulator does not model, with what is
+/// simulated instead. A key only counts if its value is not `null`, `false` or `0`.
+const UNSUPPORTED_KEYS: &[(&str, &str)] = &[
+    (
+        "sliding_window",
+        "sliding-window attention is simulated as full attention",
+    ),
+    (
+        "num_local_experts",
+        "mixture of experts is simulated as a single dense FFN",
+    ),
+    (
+        "num_experts",
+        "mixture of experts is simulated as a single dense FFN",
+    ),
+    ("rope_scaling", "RoPE scaling is ignored"),
+    ("alibi", "ALiBi position biases are ignored"),
+    (
+        "parallel_attn",
+        "parallel attention and FFN blocks are simulated sequentially",
+    ),
+    (
+        "quantization_config",
+        "quantized weights are simulated at `precision`",
+    ),
+];
+
+/// The model fields read from a HuggingFace config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedModel {
+    pub fields: Map<String, Value>,
+    /// Features of the model that the import had to drop.
+    pub warnings: Vec<String>,
+}
+
+/// Whether `table` looks like a HuggingFace config rather than a NeuPIMs model config.
+pub fn is_hf_config(table: &Map<String, Value>) -> bool {
+    table.contains_key("model_type") || table.contains_key("architectures")
+}
+
+/// Translates a HuggingFace config read from `path` into model fields.
+pub fn import_hf_config(
+    path: &Path,
+    table: Map<String, Value>,
+) -> Result<ImportedModel, ConfigError> {
+    let hf: HfConfig =
+        serde_json::from_value(Value::Object(table)).map_err(|source| ConfigError::Json {
+            path: path.to_owned(),
+            source,
+        })?;
+    let mut warnings = vec![];
+
+    let activation = match hf.hidden_act.as_deref().map(str::to_ascii_lowercase) {
+        None => Activation::default(),
+        Some(act) if act.starts_with("gelu") => Activation::Gelu,
+        Some(act) if act == "silu" || act == "swish" => Activation::Swiglu,
+        Some(act) => Activation::from_name(&act).unwrap_or_else(|err| {
+            warnings.push(format!("{err}, simulating it as gelu"));
+            Activation::Gelu
+        }),
+    };
+    if let Some(head_dim) = hf.rest.get("head_dim").and_then(Value::as_u64) {
+        if head_dim != (hf.hidden_size / hf.num_attention_heads.max(1)) as u64 {
+            warnings.push(format!(
+                "head_dim {head_dim} differs from hidden_size / num_attention_heads, which is used instead"
+            ));
+        }
+    }
+    for (key, consequence) in UNSUPPORTED_KEYS {
+        let used = match hf.rest.get(*key) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+            Some(_) => true,
+        };
+        if used {
+            warnings.push(format!("`{key}`: {consequence}"));
+        }
+    }
+    for warning in &warnings {
+        warn!("{}: {warning}", path.display());
+    }
+
+    let model_name = hf.name_or_path.clone().unwrap_or_else(|| {
+        path.parent()
+            .and_then(Path::file_name)
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let mut config = SimulationConfig {
+        model_n_embd: hf.hidden_size,
+        model_n_head: hf.num_attention_heads,
+        model_n_kv_head: hf.num_key_value_heads.unwrap_or(hf.num_attention_heads),
+        model_n_layer: hf.num_hidden_layers,
+        model_ffn_hidden: hf.intermediate_size.unwrap_or(4 * hf.hidden_size),
+        model_vocab_size: hf.vocab_size,
+        model_block_size: hf.max_position_embeddings.unwrap_or_default(),
+        model_activation: activation,
+        model_tied_embeddings: hf.tie_word_embeddings,
+        ..Default::default()
+    };
+    config.model_params_b = (config.model_params() as f64 / 1e9).round() as u32;
+
+    let fields = [
+        ("model_name", Value::from(model_name)),
+        ("model_params_b", config.model_params_b.into()),
+        ("model_vocab_size", config.model_vocab_size.into()),
+        ("model_block_size", config.model_block_size.into()),
+        ("model_n_layer", config.model_n_layer.into()),
+        ("model_n_head", config.model_n_head.into()),
+        ("model_n_kv_head", config.model_n_kv_head.into()),
+        ("model_n_embd", config.model_n_embd.into()),
+        ("model_ffn_hidden", config.model_ffn_hidden.into()),
+        ("model_activation", activation.name().into()),
+        ("model_tied_embeddings", config.model_tied_embeddings.into()),
+    ];
+    Ok(ImportedModel {
+        fields: fields
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect(),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn import(config: Value) -> ImportedModel {
+        let Value::Object(table) = config else {
+            unreachable!()
+        };
+        assert!(is_hf_config(&table));
+        import_hf_config(Path::new("mistral/config.json"), table).unwrap()
+    }
+
+    #[test]
+    fn test_import_mistral() {
+        let model = import(json!({
+            "architectures": ["MistralForCausalLM"],
+            "hidden_act": "silu",
+            "hidden_size": 4096,
+            "intermediate_size": 14336,
+            "max_position_embeddings": 32768,
+            "model_type": "mistral",
+            "num_attention_heads": 32,
+            "num_hidden_layers": 32,
+            "num_key_value_heads": 8,
+            "rope_theta": 10000.0,
+            "sliding_window": 4096,
+            "tie_word_embeddings": false,
+            "vocab_size": 32000
+        }));
+        assert_eq!(model.fields["model_name"], "mistral");
+        assert_eq!(model.fields["model_params_b"], 7);
+        assert_eq!(model.fields["model_n_kv_head"], 8);
+        assert_eq!(model.fields["model_activation"], "swiglu");
+        assert_eq!(
+            model.warnings,
+            vec!["`sliding_window`: sliding-window attention is simulated as full attention"]
+        );
+    }
+
+    #[test]
+    fn test_import_gpt2_spelling() {
+        let model = import(json!({
+            "_name_or_path": "gpt2-medium",
+            "activation_function": "gelu_new",
+            "model_type": "gpt2",
+            "n_ctx": 1024,
+            "n_embd": 1024,
+            "n_head": 16,
+            "n_layer": 24,
+            "n_positions": 1024,
+            "vocab_size": 50257
+        }));
+        assert_eq!(model.fields["model_name"], "gpt2-medium");
+        assert_eq!(model.fields["model_n_kv_head"], 16);
+        assert_eq!(model.fields["model_ffn_hidden"], 4096);
+        assert_eq!(model.fields["model_tied_embeddings"], true);
+        assert!(model.warnings.is_empty());
+    }
+}
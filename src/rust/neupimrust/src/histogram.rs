@@ -0,0 +1,293 @@
+//! Histograms of busy and idle period lengths.
+//!
+//! Keying a histogram by the exact cycle count produces one bucket per distinct length, which on
+//! long runs means tens of thousands of singleton buckets. A [`HistogramStrategy`] groups the
+//! lengths instead into fixed-width buckets or, HDR-style, into buckets whose width grows with
+//! the length so that the relative error stays bounded. Percentiles are computed from the
+//! buckets and reported with the histogram.
+
+use std::{collections::BTreeMap, fmt, num::NonZeroU64};
+
+use schemars::{gen::SchemaGenerator, schema::Schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::activity::Cycle;
+
+/// How lengths are grouped into buckets.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum HistogramStrategy {
+    /// One bucket per distinct length.
+    #[default]
+    Exact,
+    /// Buckets of `width` cycles.
+    Linear { width: NonZeroU64 },
+    /// Every power-of-two range `[2^k, 2^(k+1))` split into `sub_buckets` equal buckets, so the
+    /// relative error is at most `1 / sub_buckets`. Lengths below `sub_buckets` are exact.
+    Log2 { sub_buckets: SubBuckets },
+}
+
+/// The number of buckets per power-of-two range of [`HistogramStrategy::Log2`], a power of two
+/// so that every bucket of a range has the same width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u64", into = "u64")]
+pub struct SubBuckets(u64);
+
+impl SubBuckets {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl TryFrom<u64> for SubBuckets {
+    type Error = InvalidSubBuckets;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        if value.is_power_of_two() {
+            Ok(SubBuckets(value))
+        } else {
+            Err(InvalidSubBuckets(value))
+        }
+    }
+}
+
+impl From<SubBuckets> for u64 {
+    fn from(value: SubBuckets) -> u64 {
+        value.0
+    }
+}
+
+impl schemars::JsonSchema for SubBuckets {
+    fn schema_name() -> String {
+        "SubBuckets".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        serde_json::from_value(serde_json::json!({
+            "description": "A power of two",
+            "type": "integer",
+            "format": "uint64",
+            "minimum": 1,
+        }))
+        .expect("a valid schema")
+    }
+}
+
+/// A `sub_buckets` that is not a power of two.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSubBuckets(pub u64);
+
+impl fmt::Display for InvalidSubBuckets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sub_buckets must be a power of two, got {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidSubBuckets {}
+
+impl HistogramStrategy {
+    /// The first and the last length of the bucket containing `value`.
+    pub fn bucket(self, value: u64) -> (u64, u64) {
+        let (base, width) = match self {
+            HistogramStrategy::Exact => (0, 1),
+            HistogramStrategy::Linear { width } => (0, width.get()),
+            HistogramStrategy::Log2 { sub_buckets } if value < sub_buckets.get() => (0, 1),
+            HistogramStrategy::Log2 { sub_buckets } => {
+                let base = 1 << value.ilog2();
+                (base, base / sub_buckets.get())
+            }
+        };
+        let start = base + (value - base) / width * width;
+        (start, start + width - 1)
+    }
+}
+
+/// The number of periods per bucket, keyed by the first length of the bucket.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    strategy: HistogramStrategy,
+    buckets: BTreeMap<Cycle, u64>,
+    count: u64,
+    sum: u64,
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+impl Histogram {
+    pub fn new(strategy: HistogramStrategy) -> Self {
+        Histogram {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&mut self, value: u64) {
+        let (start, _) = self.strategy.bucket(value);
+        *self.buckets.entry(Cycle(start)).or_default() += 1;
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    pub fn strategy(&self) -> HistogramStrategy {
+        self.strategy
+    }
+
+    pub fn buckets(&self) -> &BTreeMap<Cycle, u64> {
+        &self.buckets
+    }
+
+    /// The number of recorded periods.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// The length that `p` percent of the periods do not exceed, `p` in `0..=100`.
+    ///
+    /// With bucketed strategies this is the last length of the bucket holding the percentile,
+    /// capped at the longest recorded period, so it never underestimates.
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        let rank = ((p / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count.max(1));
+        let mut seen = 0;
+        for (&Cycle(start), &count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                let (_, end) = self.strategy.bucket(start);
+                return Some(end.min(self.max.unwrap_or(end)));
+            }
+        }
+        None
+    }
+}
+
+impl Serialize for Histogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Summary<'a> {
+            strategy: HistogramStrategy,
+            count: u64,
+            sum: u64,
+            min: Option<u64>,
+            max: Option<u64>,
+            p50: Option<u64>,
+            p90: Option<u64>,
+            p99: Option<u64>,
+            buckets: &'a BTreeMap<Cycle, u64>,
+        }
+        Summary {
+            strategy: self.strategy,
+            count: self.count,
+            sum: self.sum,
+            min: self.min,
+            max: self.max,
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+            p99: self.percentile(99.0),
+            buckets: &self.buckets,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Histogram {
+    /// Reads the summary written by `Serialize`; the percentiles are derived from the buckets.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Summary {
+            strategy: HistogramStrategy,
+            count: u64,
+            sum: u64,
+            min: Option<u64>,
+            max: Option<u64>,
+            buckets: BTreeMap<Cycle, u64>,
+        }
+        let summary = Summary::deserialize(deserializer)?;
+        Ok(Histogram {
+            strategy: summary.strategy,
+            buckets: summary.buckets,
+            count: summary.count,
+            sum: summary.sum,
+            min: summary.min,
+            max: summary.max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        let log2 = HistogramStrategy::Log2 {
+            sub_buckets: SubBuckets::try_from(4).unwrap(),
+        };
+        assert_eq!(log2.bucket(0), (0, 0));
+        assert_eq!(log2.bucket(3), (3, 3));
+        assert_eq!(log2.bucket(5), (5, 5));
+        assert_eq!(log2.bucket(17), (16, 19));
+        assert_eq!(log2.bucket(1000), (896, 1023));
+        let linear = HistogramStrategy::Linear {
+            width: NonZeroU64::new(10).unwrap(),
+        };
+        assert_eq!(linear.bucket(25), (20, 29));
+        assert_eq!(HistogramStrategy::Exact.bucket(25), (25, 25));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut exact = Histogram::new(HistogramStrategy::Exact);
+        let mut log2 = Histogram::new(HistogramStrategy::Log2 {
+            sub_buckets: SubBuckets::try_from(8).unwrap(),
+        });
+        for value in 1..=100 {
+            exact.record(value);
+            log2.record(value);
+        }
+        assert_eq!(exact.percentile(50.0), Some(50));
+        assert_eq!(exact.percentile(99.0), Some(99));
+        assert_eq!(exact.percentile(100.0), Some(100));
+        // 50 falls into [48, 51], 99 into [96, 103] capped at the maximum
+        assert_eq!(log2.percentile(50.0), Some(51));
+        assert_eq!(log2.percentile(99.0), Some(100));
+        assert_eq!(log2.buckets().len(), 36);
+        assert_eq!(Histogram::default().percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_sub_buckets_must_be_a_power_of_two() {
+        let parse = |sub_buckets: u64| {
+            serde_json::from_value::<HistogramStrategy>(
+                serde_json::json!({ "kind": "log2", "sub_buckets": sub_buckets }),
+            )
+        };
+        let err = parse(0).unwrap_err();
+        assert!(
+            err.to_string().contains("must be a power of two, got 0"),
+            "{err}"
+        );
+        let err = parse(6).unwrap_err();
+        assert!(
+            err.to_string().contains("must be a power of two, got 6"),
+            "{err}"
+        );
+        let strategy = parse(1).unwrap();
+        assert_eq!(strategy.bucket(5), (4, 7));
+    }
+
+    #[test]
+    fn test_linear_width_must_be_non_zero() {
+        let err = serde_json::from_value::<HistogramStrategy>(
+            serde_json::json!({ "kind": "linear", "width": 0 }),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("expected a nonzero u64"), "{err}");
+    }
+}
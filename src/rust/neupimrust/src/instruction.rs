@@ -8,6 +8,12 @@ pub struct Tile {
     instructions: Vec<Instruction>,
 }
 
+impl Default for Tile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Tile {
     pub fn new() -> Self {
         Tile {
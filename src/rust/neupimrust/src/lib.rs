@@ -1,13 +1,49 @@
-use clap::Parser;
+// The `extern "C"` entry points take raw pointers from the C++ side by design.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use config_loader::{load_config_with_overrides, ConfigFiles, ConfigOverride};
+use sweep::SweepSpec;
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::EnvFilter;
+pub mod activity;
 pub mod allocator;
+pub mod config_loader;
+pub mod config_validation;
+pub mod config_view;
+pub mod energy;
+pub mod event_log;
+pub mod footprint;
 pub mod global_config;
 pub mod global_counts;
+pub mod hf_import;
+pub mod histogram;
 pub mod instruction;
+pub mod model_zoo;
 pub mod no_icnt;
+pub mod overlap;
+pub mod provenance;
+pub mod registry;
+pub mod report;
+pub mod roofline;
+pub mod sampler;
+pub mod schema;
 pub mod settings;
+pub mod sweep;
 pub mod tensor;
+pub mod trace;
+pub mod units;
+
+/// A fresh directory for the files of one test. It is keyed on the process id, so concurrent test
+/// runs do not collide.
+#[cfg(test)]
+pub(crate) fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("neupimrust_test_{}_{test}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
 #[repr(C)]
 pub enum LogLevel {
     Debug,
@@ -29,13 +65,261 @@ impl From<LogLevel> for LevelFilter {
 
 #[derive(Parser)]
 struct Cli {
-    sjqconfig: String,
-    config: String,
-    mem_config: String,
-    cli_config: String,
-    model_config: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Load and validate a configuration, reporting where each field came from
+    Check(ConfigArgs),
+    /// Expand a sweep spec into one resolved configuration per point
+    Sweep(SweepArgs),
+    /// Convert a HuggingFace `config.json` into a model configuration file
+    ImportHf(ImportHfArgs),
+    /// Report the HBM footprint of the configured model and whether it fits
+    Footprint(ConfigArgs),
+    /// Print or write the JSON Schemas of the config files
+    Schema(SchemaArgs),
+    /// Convert an `events.jsonl` into a Chrome Trace for Perfetto
+    Trace(TraceArgs),
+    /// Report how NPU and PIM overlapped in each stage of an `events.jsonl`
+    Overlap(OverlapArgs),
+    /// Summarize the `counts.json` of a past run
+    Counts(CountsArgs),
+}
+
+#[derive(Args)]
+#[command(rename_all = "snake_case")]
+struct ConfigArgs {
+    /// Path for the simulator settings file
+    #[arg(long)]
+    sjqconfig: Option<PathBuf>,
+    /// Path for hardware configuration file
+    #[arg(long)]
+    config: PathBuf,
+    /// Path for memory configuration file
+    #[arg(long)]
+    mem_config: PathBuf,
+    /// Path for client configuration file
+    #[arg(long)]
+    cli_config: PathBuf,
+    /// Path for model configuration file
+    #[arg(long)]
+    model_config: PathBuf,
+    /// Path for system configuration file
+    #[arg(long)]
+    sys_config: PathBuf,
+    /// TOML files overriding fields of the configuration files, applied in order
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+    /// Override a single field, e.g. `--set max_batch_size=256`; applied after `NEUPIMS_CFG_*`
+    /// environment variables
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    sets: Vec<ConfigOverride>,
+}
+
+impl ConfigArgs {
+    fn config_files(&self) -> ConfigFiles {
+        ConfigFiles {
+            config: Some(self.config.clone()),
+            mem_config: Some(self.mem_config.clone()),
+            model_config: Some(self.model_config.clone()),
+            sys_config: Some(self.sys_config.clone()),
+            cli_config: Some(self.cli_config.clone()),
+            overrides: self.overrides.clone(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct SweepArgs {
+    /// Path for the sweep spec file
+    spec: PathBuf,
+    /// Run directory receiving one sub-directory per sweep point
+    #[arg(long, short)]
+    out: PathBuf,
+    /// Only list the points without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct ImportHfArgs {
+    /// Path for the HuggingFace `config.json`
+    path: PathBuf,
+    /// Where to write the model configuration; printed to stdout if omitted
+    #[arg(long, short)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SchemaArgs {
+    /// Which schema to print: simulation_config, settings or sweep_spec
+    #[arg(default_value = "simulation_config")]
+    name: String,
+    /// Write every schema as `<name>.schema.json` into this directory instead
+    #[arg(long, short)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct TraceArgs {
+    /// Path for the `events.jsonl` written by the simulator
+    events: PathBuf,
+    /// Where to write the trace
+    #[arg(long, short, default_value = "trace.json")]
+    out: PathBuf,
+}
+
+#[derive(Args)]
+struct OverlapArgs {
+    /// Path for the `events.jsonl` written by the simulator. The report is only complete if the
+    /// `[event_log]` filter kept the NPU, PIM and stage events
+    events: PathBuf,
+    /// Also write the report as JSON to this file
+    #[arg(long, short)]
+    out: Option<PathBuf>,
 }
-pub fn run() {}
+
+#[derive(Args)]
+struct CountsArgs {
+    /// Path for the `counts.json` or `counts.toml` written by the simulator
+    counts: PathBuf,
+    /// Also print the events with a cycle in `FROM..TO` as JSON lines
+    #[arg(long, num_args = 2, value_names = ["FROM", "TO"])]
+    events: Option<Vec<u64>>,
+}
+
+/// Entry point of the `neupimsim` binary.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    match Cli::parse().command {
+        Command::Check(args) => check(&args),
+        Command::Sweep(args) => sweep(&args),
+        Command::ImportHf(args) => import_hf(&args),
+        Command::Footprint(args) => footprint(&args),
+        Command::Schema(args) => print_schema(&args),
+        Command::Trace(args) => trace(&args),
+        Command::Overlap(args) => overlap(&args),
+        Command::Counts(args) => counts(&args),
+    }
+}
+
+/// Loads the configuration, reports where each field came from and validates it.
+fn check(args: &ConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(sjqconfig) = &args.sjqconfig {
+        settings::init_settings_with_file_(sjqconfig)?;
+    }
+    let loaded = load_config_with_overrides(&args.config_files(), &args.sets)?;
+    for (field, source) in &loaded.sources {
+        info!("{field:<28} <- {source}");
+    }
+    info!("{:#?}", loaded.config);
+    loaded.config.validate()?;
+    Ok(())
+}
+
+fn sweep(args: &SweepArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let spec = SweepSpec::from_file(&args.spec)?;
+    if args.dry_run {
+        for point in spec.points()? {
+            info!("{}: {:?}", point.dir_name(), point.values);
+        }
+        return Ok(());
+    }
+    spec.materialize(&args.out)?;
+    Ok(())
+}
+
+fn footprint(args: &ConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config_with_overrides(&args.config_files(), &args.sets)?.config;
+    let footprint = config.footprint();
+    println!("{footprint}");
+    if !footprint.fits() {
+        return Err(format!("{} does not fit", config.model_name).into());
+    }
+    Ok(())
+}
+
+fn print_schema(args: &SchemaArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(out) = &args.out {
+        for path in schema::write_schemas(out)? {
+            info!("wrote {}", path.display());
+        }
+        return Ok(());
+    }
+    let schemas = schema::config_schemas();
+    match schemas.iter().find(|(name, _)| *name == args.name) {
+        Some((_, schema)) => print!("{}", schema::schema_to_string(schema)),
+        None => {
+            let names: Vec<_> = schemas.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "unknown schema `{}`, valid choices are: {}",
+                args.name,
+                names.join(", ")
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn trace(args: &TraceArgs) -> Result<(), Box<dyn std::error::Error>> {
+    trace::trace_from_file(&args.events)?.write_to_file(&args.out)?;
+    info!("wrote {}", args.out.display());
+    Ok(())
+}
+
+fn overlap(args: &OverlapArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let report = overlap::OverlapReport::from_events(&event_log::read_events(&args.events)?);
+    println!("{report}");
+    if let Some(out) = &args.out {
+        report.write_to_file(out)?;
+    }
+    Ok(())
+}
+
+fn counts(args: &CountsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let report::LoadedCounts {
+        run_info, counts, ..
+    } = report::load_counts(&args.counts)?;
+    println!("{run_info}, {} cycles", counts.last_cycle);
+    for (op, activity) in counts.activity.iter() {
+        let ratio = activity.busy_ratio().unwrap_or(0.0);
+        println!(
+            "{:<12} {:>12} ops, busy {:>5.1}%",
+            format!("{op:?}"),
+            activity.total,
+            ratio * 100.0
+        );
+    }
+    for stage in counts.stage_breakdown() {
+        println!(
+            "stage {:<9} {:>12} cycles {:>5.1}%",
+            format!("{:?}", stage.stage),
+            stage.counts.cycles,
+            stage.share * 100.0
+        );
+    }
+    if let Some(&[from, to]) = args.events.as_deref() {
+        for event in counts.events_in_window(from, to)? {
+            println!("{}", serde_json::to_string(&event)?);
+        }
+    }
+    Ok(())
+}
+
+fn import_hf(args: &ImportHfArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let table = serde_json::from_str(&std::fs::read_to_string(&args.path)?)?;
+    let model = hf_import::import_hf_config(&args.path, table)?;
+    let json = serde_json::to_string_pretty(&model.fields)?;
+    match &args.out {
+        Some(out) => std::fs::write(out, json + "\n")?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
 /// 初始化日志记录器
 ///
 /// # 参数
@@ -0,0 +1,272 @@
+//! Built-in model presets and the model dimensions derived from a config.
+//!
+//! A config whose `model_name` matches a preset gets the preset's architecture for every model
+//! field it does not set itself, so `"model_name": "Llama-2-70B"` alone describes the model. The
+//! shipped GPT-3 configs still set `model_n_layer` to 1 to simulate a single layer.
+
+use serde_json::{Map, Value};
+
+use crate::global_config::{Activation, SimulationConfig};
+
+/// The architecture of a published model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelPreset {
+    pub name: &'static str,
+    /// Other accepted names, e.g. the HuggingFace model id.
+    pub aliases: &'static [&'static str],
+    pub params_b: u32,
+    pub vocab_size: u32,
+    /// Maximum context length.
+    pub block_size: u32,
+    pub n_layer: u32,
+    pub n_head: u32,
+    pub n_kv_head: u32,
+    pub n_embd: u32,
+    pub ffn_hidden: u32,
+    pub activation: Activation,
+    pub tied_embeddings: bool,
+}
+
+const fn gpt3(
+    name: &'static str,
+    params_b: u32,
+    n_layer: u32,
+    n_head: u32,
+    n_embd: u32,
+) -> ModelPreset {
+    ModelPreset {
+        name,
+        aliases: &[],
+        params_b,
+        vocab_size: 50304,
+        block_size: 2048,
+        n_layer,
+        n_head,
+        n_kv_head: n_head,
+        n_embd,
+        ffn_hidden: 4 * n_embd,
+        activation: Activation::Gelu,
+        tied_embeddings: true,
+    }
+}
+
+const fn opt(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    params_b: u32,
+    n_layer: u32,
+    n_head: u32,
+    n_embd: u32,
+) -> ModelPreset {
+    ModelPreset {
+        name,
+        aliases,
+        params_b,
+        vocab_size: 50272,
+        block_size: 2048,
+        n_layer,
+        n_head,
+        n_kv_head: n_head,
+        n_embd,
+        ffn_hidden: 4 * n_embd,
+        activation: Activation::Relu,
+        tied_embeddings: true,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn llama(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    params_b: u32,
+    vocab_size: u32,
+    block_size: u32,
+    n_layer: u32,
+    n_head: u32,
+    n_kv_head: u32,
+    n_embd: u32,
+    ffn_hidden: u32,
+) -> ModelPreset {
+    ModelPreset {
+        name,
+        aliases,
+        params_b,
+        vocab_size,
+        block_size,
+        n_layer,
+        n_head,
+        n_kv_head,
+        n_embd,
+        ffn_hidden,
+        activation: Activation::Swiglu,
+        tied_embeddings: false,
+    }
+}
+
+/// Every built-in preset.
+#[rustfmt::skip]
+pub const MODEL_PRESETS: &[ModelPreset] = &[
+    //   name        params_b  n_layer  n_head  n_embd
+    gpt3("GPT3-7B",     7,       32,      32,     4096),
+    gpt3("GPT3-13B",    13,      40,      40,     5120),
+    gpt3("GPT3-30B",    30,      48,      56,     7168),
+    gpt3("GPT3-175B",   175,     96,      96,     12288),
+    //    name           aliases                          params_b  vocab   block  n_layer  n_head  n_kv_head  n_embd  ffn_hidden
+    llama("Llama-2-7B",  &["meta-llama/Llama-2-7b-hf"],    7,        32000,  4096,  32,      32,     32,        4096,   11008),
+    llama("Llama-2-13B", &["meta-llama/Llama-2-13b-hf"],   13,       32000,  4096,  40,      40,     40,        5120,   13824),
+    llama("Llama-2-70B", &["meta-llama/Llama-2-70b-hf"],   70,       32000,  4096,  80,      64,     8,         8192,   28672),
+    llama("Llama-3-8B",  &["meta-llama/Meta-Llama-3-8B"],  8,        128256, 8192,  32,      32,     8,         4096,   14336),
+    llama("Llama-3-70B", &["meta-llama/Meta-Llama-3-70B"], 70,       128256, 8192,  80,      64,     8,         8192,   28672),
+    llama("Mistral-7B",  &["mistralai/Mistral-7B-v0.1"],   7,        32000,  32768, 32,      32,     8,         4096,   14336),
+    //   name        aliases                 params_b  n_layer  n_head  n_embd
+    opt("OPT-6.7B",  &["facebook/opt-6.7b"], 7,        32,      32,     4096),
+    opt("OPT-13B",   &["facebook/opt-13b"],  13,       40,      40,     5120),
+    opt("OPT-30B",   &["facebook/opt-30b"],  30,       48,      56,     7168),
+    opt("OPT-66B",   &["facebook/opt-66b"],  66,       64,      72,     9216),
+];
+
+impl ModelPreset {
+    /// Looks up a preset by name or alias, ignoring case.
+    pub fn find(name: &str) -> Option<&'static ModelPreset> {
+        MODEL_PRESETS.iter().find(|preset| {
+            std::iter::once(&preset.name)
+                .chain(preset.aliases)
+                .any(|candidate| candidate.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// The preset as config fields.
+    pub fn fields(&self) -> Map<String, Value> {
+        let fields = [
+            ("model_params_b", Value::from(self.params_b)),
+            ("model_vocab_size", self.vocab_size.into()),
+            ("model_block_size", self.block_size.into()),
+            ("model_n_layer", self.n_layer.into()),
+            ("model_n_head", self.n_head.into()),
+            ("model_n_kv_head", self.n_kv_head.into()),
+            ("model_n_embd", self.n_embd.into()),
+            ("model_ffn_hidden", self.ffn_hidden.into()),
+            (
+                "model_activation",
+                serde_json::to_value(self.activation).expect("activation is serializable"),
+            ),
+            ("model_tied_embeddings", self.tied_embeddings.into()),
+        ];
+        fields
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect()
+    }
+}
+
+impl SimulationConfig {
+    /// The number of KV heads, `model_n_head` unless the model uses GQA or MQA.
+    pub fn n_kv_head(&self) -> u32 {
+        match self.model_n_kv_head {
+            0 => self.model_n_head,
+            n => n,
+        }
+    }
+
+    pub fn head_dim(&self) -> u32 {
+        self.model_n_embd
+            .checked_div(self.model_n_head)
+            .unwrap_or(0)
+    }
+
+    /// The hidden size of the feed-forward block.
+    pub fn ffn_hidden(&self) -> u32 {
+        match self.model_ffn_hidden {
+            0 => 4 * self.model_n_embd,
+            n => n,
+        }
+    }
+
+    /// The number of weights of one decoder layer, without biases.
+    pub fn layer_params(&self) -> u64 {
+        let embd = self.model_n_embd as u64;
+        let kv_width = (self.n_kv_head() * self.head_dim()) as u64;
+        let qkv = embd * (embd + 2 * kv_width);
+        let projection = embd * embd;
+        let ffn_matrices = if self.model_activation.is_gated() {
+            3
+        } else {
+            2
+        };
+        let ffn = ffn_matrices * embd * self.ffn_hidden() as u64;
+        let layer_norms = 2 * embd;
+        qkv + projection + ffn + layer_norms
+    }
+
+    /// The number of weights of the simulated layers plus the embeddings.
+    pub fn model_params(&self) -> u64 {
+        let embeddings = self.model_vocab_size as u64 * self.model_n_embd as u64;
+        let lm_head = if self.model_tied_embeddings {
+            0
+        } else {
+            embeddings
+        };
+        self.model_n_layer as u64 * self.layer_params() + embeddings + lm_head
+    }
+
+    /// The size of the keys and values of one token over all layers, in bytes.
+    pub fn kv_cache_bytes_per_token(&self) -> u64 {
+        2 * self.model_n_layer as u64
+            * self.n_kv_head() as u64
+            * self.head_dim() as u64
+            * self.precision as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str) -> SimulationConfig {
+        let mut fields = SimulationConfig::default_fields();
+        fields.extend(ModelPreset::find(name).unwrap().fields());
+        SimulationConfig {
+            precision: 2,
+            ..serde_json::from_value(Value::Object(fields)).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_find_by_alias() {
+        assert_eq!(ModelPreset::find("gpt3-7b").unwrap().name, "GPT3-7B");
+        assert_eq!(
+            ModelPreset::find("meta-llama/Llama-2-70b-hf").unwrap().name,
+            "Llama-2-70B"
+        );
+        assert!(ModelPreset::find("GPT-5").is_none());
+    }
+
+    #[test]
+    fn test_params_match_published_sizes() {
+        for preset in MODEL_PRESETS {
+            let params = config(preset.name).model_params() as f64 / 1e9;
+            let published = preset.params_b as f64;
+            assert!(
+                (params - published).abs() / published < 0.1,
+                "{}: {params:.2}B parameters",
+                preset.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_gqa_shrinks_kv_cache() {
+        let llama2 = config("Llama-2-70B");
+        assert_eq!(llama2.n_kv_head(), 8);
+        assert_eq!(llama2.kv_cache_bytes_per_token(), 2 * 80 * 8 * 128 * 2);
+
+        let mha = SimulationConfig {
+            model_n_kv_head: 0,
+            ..llama2.clone()
+        };
+        assert_eq!(
+            mha.kv_cache_bytes_per_token(),
+            8 * llama2.kv_cache_bytes_per_token()
+        );
+    }
+}
@@ -5,6 +5,12 @@ pub struct NoIcnt {
     total_packages: usize,
 }
 
+impl Default for NoIcnt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NoIcnt {
     pub fn new() -> Self {
         NoIcnt { total_packages: 0 }
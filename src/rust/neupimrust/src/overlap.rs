@@ -0,0 +1,382 @@
+//! NPU/PIM overlap analysis of the event stream.
+//!
+//! NeuPIMs hides the GEMVs of one sub-batch on PIM behind the GEMMs of the other on the NPU. The
+//! NPU program of a stage runs from `NpuStart` to `NpuFinished` and the PIM program from
+//! `PimStart` to `PimFinished`. Sweeping over the events splits the run, and every stage, into
+//! NPU-only, PIM-only, overlapped and idle cycles. The platform that finishes last in a stage is
+//! on its critical path: shortening the other one would not make the stage any faster.
+//!
+//! The simulator feeds every NPU, PIM and stage event to an [`OverlapTracker`] before the
+//! `[event_log]` filter sees it, so `overlap.json` is complete however much of `events.jsonl` is
+//! filtered out.
+
+use std::{fmt, path::Path};
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::{
+    global_counts::{Event, EventType, GlobalCountsCtx, RunStage},
+    provenance::{RunInfo, WithRunInfo},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Platform {
+    Npu,
+    Pim,
+}
+
+/// How the cycles of a time span were used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Utilization {
+    pub npu_only: u64,
+    pub pim_only: u64,
+    pub overlapped: u64,
+    pub idle: u64,
+}
+
+impl Utilization {
+    fn add(&mut self, npu_busy: bool, pim_busy: bool, cycles: u64) {
+        match (npu_busy, pim_busy) {
+            (true, false) => self.npu_only += cycles,
+            (false, true) => self.pim_only += cycles,
+            (true, true) => self.overlapped += cycles,
+            (false, false) => self.idle += cycles,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.npu_only + self.pim_only + self.overlapped + self.idle
+    }
+
+    /// The share of the busy cycles during which both platforms were busy.
+    pub fn overlap_ratio(&self) -> f64 {
+        let busy = self.npu_only + self.pim_only + self.overlapped;
+        if busy == 0 {
+            return 0.0;
+        }
+        self.overlapped as f64 / busy as f64
+    }
+}
+
+/// One execution of a stage.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageSummary {
+    pub stage: RunStage,
+    pub start: u64,
+    /// `None` if the run ended during the stage.
+    pub end: Option<u64>,
+    pub duration: Option<u64>,
+    pub utilization: Utilization,
+    /// When the NPU program of the stage finished.
+    pub npu_finished: Option<u64>,
+    /// When the PIM program of the stage finished.
+    pub pim_finished: Option<u64>,
+    /// The platform that finished last, `None` if neither finished or both finished together.
+    pub critical_path: Option<Platform>,
+    /// How long the critical platform ran after the other one finished.
+    pub slack: Option<u64>,
+}
+
+impl StageSummary {
+    fn new(stage: RunStage, start: u64) -> Self {
+        StageSummary {
+            stage,
+            start,
+            end: None,
+            duration: None,
+            utilization: Utilization::default(),
+            npu_finished: None,
+            pim_finished: None,
+            critical_path: None,
+            slack: None,
+        }
+    }
+
+    fn finish(&mut self, end: u64) {
+        self.end = Some(end);
+        self.duration = Some(end - self.start);
+        if let (Some(npu), Some(pim)) = (self.npu_finished, self.pim_finished) {
+            self.critical_path = match npu.cmp(&pim) {
+                std::cmp::Ordering::Greater => Some(Platform::Npu),
+                std::cmp::Ordering::Less => Some(Platform::Pim),
+                std::cmp::Ordering::Equal => None,
+            };
+            self.slack = Some(npu.abs_diff(pim));
+        } else if self.npu_finished.is_some() {
+            self.critical_path = Some(Platform::Npu);
+        } else if self.pim_finished.is_some() {
+            self.critical_path = Some(Platform::Pim);
+        }
+    }
+}
+
+/// The cycles of finished stages, by the platform on their critical path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CriticalPathCycles {
+    pub npu: u64,
+    pub pim: u64,
+    /// Stages where both platforms finished together, or none did.
+    pub balanced: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct OverlapReport {
+    /// From the first to the last event.
+    pub overall: Utilization,
+    pub overlap_ratio: f64,
+    pub critical_path_cycles: CriticalPathCycles,
+    pub stages: Vec<StageSummary>,
+}
+
+/// Builds an [`OverlapReport`] one event at a time.
+#[derive(Debug, Clone, Default)]
+pub struct OverlapTracker {
+    report: OverlapReport,
+    npu_busy: bool,
+    pim_busy: bool,
+    /// The stage being executed, as an index into `report.stages`.
+    current: Option<usize>,
+    /// The cycle of the latest event, `None` before the first one.
+    last_cycle: Option<u64>,
+}
+
+impl OverlapTracker {
+    pub fn push(&mut self, event: &Event) {
+        let report = &mut self.report;
+        let last_cycle = self.last_cycle.unwrap_or(event.cycle);
+        let cycles = event.cycle.saturating_sub(last_cycle);
+        report.overall.add(self.npu_busy, self.pim_busy, cycles);
+        if let Some(index) = self.current {
+            report.stages[index]
+                .utilization
+                .add(self.npu_busy, self.pim_busy, cycles);
+        }
+        self.last_cycle = Some(last_cycle.max(event.cycle));
+
+        match event.event {
+            EventType::NpuStart => self.npu_busy = true,
+            EventType::PimStart => self.pim_busy = true,
+            EventType::NpuFinished => {
+                self.npu_busy = false;
+                if let Some(index) = self.current {
+                    report.stages[index].npu_finished = Some(event.cycle);
+                }
+            }
+            EventType::PimFinished => {
+                self.pim_busy = false;
+                if let Some(index) = self.current {
+                    report.stages[index].pim_finished = Some(event.cycle);
+                }
+            }
+            EventType::StageStart => {
+                report
+                    .stages
+                    .push(StageSummary::new(event.stage, event.cycle));
+                self.current = Some(report.stages.len() - 1);
+            }
+            EventType::StageEnd => {
+                if let Some(index) = self
+                    .current
+                    .filter(|&i| report.stages[i].stage == event.stage)
+                {
+                    report.stages[index].finish(event.cycle);
+                    self.current = None;
+                }
+            }
+            EventType::MemEventStart(_) | EventType::MemEventEnd(_) => {}
+        }
+    }
+
+    /// The report of the events pushed so far.
+    pub fn report(&self) -> OverlapReport {
+        let mut report = self.report.clone();
+        for stage in &report.stages {
+            let Some(duration) = stage.duration else {
+                continue;
+            };
+            let cycles = &mut report.critical_path_cycles;
+            match stage.critical_path {
+                Some(Platform::Npu) => cycles.npu += duration,
+                Some(Platform::Pim) => cycles.pim += duration,
+                None => cycles.balanced += duration,
+            }
+        }
+        report.overlap_ratio = report.overall.overlap_ratio();
+        report
+    }
+}
+
+impl OverlapReport {
+    pub fn from_events(events: &[Event]) -> Self {
+        let mut tracker = OverlapTracker::default();
+        for event in events {
+            tracker.push(event);
+        }
+        tracker.report()
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for OverlapReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  critical",
+            "stage", "start", "duration", "npu_only", "pim_only", "overlapped", "idle"
+        )?;
+        for stage in &self.stages {
+            let utilization = &stage.utilization;
+            writeln!(
+                f,
+                "{:<8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  {}",
+                format!("{:?}", stage.stage),
+                stage.start,
+                stage.duration.map_or("-".to_string(), |d| d.to_string()),
+                utilization.npu_only,
+                utilization.pim_only,
+                utilization.overlapped,
+                utilization.idle,
+                match stage.critical_path {
+                    Some(platform) => format!("{platform:?}"),
+                    None => "-".to_string(),
+                },
+            )?;
+        }
+        let overall = &self.overall;
+        writeln!(
+            f,
+            "{:<8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "all",
+            "",
+            overall.total(),
+            overall.npu_only,
+            overall.pim_only,
+            overall.overlapped,
+            overall.idle
+        )?;
+        write!(
+            f,
+            "overlap ratio {:.1}%, critical path cycles: NPU {}, PIM {}, balanced {}",
+            self.overlap_ratio * 100.0,
+            self.critical_path_cycles.npu,
+            self.critical_path_cycles.pim,
+            self.critical_path_cycles.balanced
+        )
+    }
+}
+
+/// 分析 NPU 和 PIM 的重叠情况，连同 [`RunInfo`] 保存到输出目录下的 `overlap.json`
+///
+/// 分析基于未经 `[event_log]` 过滤的完整事件流
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+#[no_mangle]
+pub extern "C" fn save_overlap_report(ctx: &GlobalCountsCtx) -> bool {
+    let path = ctx.out_dir.join("overlap.json");
+    let report = ctx.overlap.report();
+    match WithRunInfo::new(RunInfo::current(), &report).write_to_file(&path) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("无法写入{}: {err}", path.display());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(cycle: u64, stage: RunStage, event: EventType) -> Event {
+        Event {
+            cycle,
+            stage,
+            event,
+        }
+    }
+
+    #[test]
+    fn test_overlap() {
+        use EventType::*;
+        let events = [
+            event(0, RunStage::A, StageStart),
+            event(0, RunStage::A, NpuStart),
+            event(10, RunStage::A, PimStart),
+            event(30, RunStage::A, NpuFinished),
+            event(40, RunStage::A, PimFinished),
+            event(45, RunStage::A, StageEnd),
+            event(45, RunStage::B, StageStart),
+            event(45, RunStage::B, NpuStart),
+            event(45, RunStage::B, PimStart),
+            event(50, RunStage::B, PimFinished),
+            event(60, RunStage::B, NpuFinished),
+            event(60, RunStage::B, StageEnd),
+        ];
+        let report = OverlapReport::from_events(&events);
+        let a = &report.stages[0];
+        assert_eq!(
+            a.utilization,
+            Utilization {
+                npu_only: 10,
+                pim_only: 10,
+                overlapped: 20,
+                idle: 5,
+            }
+        );
+        assert_eq!(a.duration, Some(45));
+        assert_eq!(a.critical_path, Some(Platform::Pim));
+        assert_eq!(a.slack, Some(10));
+
+        let b = &report.stages[1];
+        assert_eq!(b.critical_path, Some(Platform::Npu));
+        assert_eq!(b.utilization.overlapped, 5);
+        assert_eq!(report.overall.total(), 60);
+        assert_eq!(report, {
+            let mut tracker = OverlapTracker::default();
+            events.iter().for_each(|event| tracker.push(event));
+            tracker.report()
+        });
+        assert_eq!(report.overall.overlapped, 25);
+        assert_eq!(
+            report.critical_path_cycles,
+            CriticalPathCycles {
+                npu: 15,
+                pim: 45,
+                balanced: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_independent_of_event_filter() {
+        use crate::event_log::{EventFilter, EventKind, EventLog};
+        use crate::global_counts::*;
+
+        let mut ctx = GlobalCountsCtx {
+            events: EventLog::in_memory(EventFilter {
+                types: vec![EventKind::MemEventStart],
+                stages: vec![],
+            }),
+            ..GlobalCountsCtx::default()
+        };
+        update_stage(&mut ctx, RunStage::A, 0);
+        npu_start(&mut ctx, 0);
+        pim_start(&mut ctx, 10);
+        npu_finished(&mut ctx, 30);
+        pim_finished(&mut ctx, 40);
+        end_stage(&mut ctx, RunStage::A, 45);
+        assert!(ctx.events.is_empty());
+
+        let report = ctx.overlap.report();
+        assert_eq!(report.stages.len(), 1);
+        assert_eq!(report.stages[0].utilization.overlapped, 20);
+        assert_eq!(report.stages[0].critical_path, Some(Platform::Pim));
+    }
+}
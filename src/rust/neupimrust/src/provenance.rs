@@ -0,0 +1,162 @@
+//! Provenance recorded with every result file.
+//!
+//! A [`RunInfo`] carries the fully resolved config and settings file of a run, a stable
+//! fingerprint of both, and the version and git commit of the simulator that produced it. Any
+//! output file can be traced back to its exact inputs, and the embedded config can be fed back to
+//! the loader.
+
+use std::{fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    global_config::{try_get_config, SimulationConfig},
+    settings::{current_settings_file, SettingsFile},
+};
+
+/// The git commit the crate was built from, as recorded by `build.rs`.
+///
+/// This is a build-time value: it is refreshed when HEAD or the index changes, so edits that
+/// were neither staged nor committed since the last build do not show up as `-dirty`.
+pub const GIT_COMMIT: &str = match option_env!("NEUPIMS_GIT_COMMIT") {
+    Some(commit) => commit,
+    None => "unknown",
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunInfo {
+    pub crate_version: String,
+    /// [`GIT_COMMIT`] of the binary that produced the result.
+    pub git_commit: String,
+    /// [`fingerprint`] of `config` and `settings` together.
+    pub config_hash: String,
+    pub config: Option<SimulationConfig>,
+    /// The whole settings file, including the event log, histogram, sampling and energy tables.
+    pub settings: Option<SettingsFile>,
+}
+
+impl RunInfo {
+    pub fn new(config: Option<SimulationConfig>, settings: Option<SettingsFile>) -> Self {
+        let config_hash = fingerprint(&(&config, &settings));
+        RunInfo {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: GIT_COMMIT.to_string(),
+            config_hash,
+            config,
+            settings,
+        }
+    }
+
+    /// Describes the current process: the global config and settings, if initialized.
+    pub fn current() -> Self {
+        Self::new(try_get_config().cloned(), current_settings_file())
+    }
+}
+
+/// Names the simulator and the config of the run, for the header of result files that cannot
+/// embed the whole [`RunInfo`].
+impl fmt::Display for RunInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "neupimsim {} ({}), config {}",
+            self.crate_version, self.git_commit, self.config_hash
+        )
+    }
+}
+
+/// A JSON result file: the [`RunInfo`] of the run that produced it, followed by the fields of
+/// the result itself.
+#[derive(Serialize)]
+pub struct WithRunInfo<'a, T> {
+    pub run_info: RunInfo,
+    #[serde(flatten)]
+    pub result: &'a T,
+}
+
+impl<'a, T: Serialize> WithRunInfo<'a, T> {
+    pub fn new(run_info: RunInfo, result: &'a T) -> Self {
+        WithRunInfo { run_info, result }
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+/// A stable content hash of `value`, as 16 hex digits.
+///
+/// The value is serialized to JSON with sorted keys and hashed with 64-bit FNV-1a, so the
+/// result only depends on the content, not on the Rust version or the platform.
+pub fn fingerprint(value: &impl Serialize) -> String {
+    let value = serde_json::to_value(value).expect("value is serializable");
+    let hash = value
+        .to_string()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let config = SimulationConfig::default();
+        let info = RunInfo::new(Some(config.clone()), None);
+        assert_eq!(
+            info.config_hash,
+            RunInfo::new(Some(config), None).config_hash
+        );
+        assert_eq!(fingerprint(&"neupims"), "cdc61b863739ff42");
+
+        let other = SimulationConfig {
+            max_batch_size: 1,
+            ..Default::default()
+        };
+        assert_ne!(
+            info.config_hash,
+            RunInfo::new(Some(other), None).config_hash
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_covers_settings_tables() {
+        let settings = SettingsFile {
+            settings: crate::settings::Settings {
+                fast_read: false,
+                fast_icnt: false,
+                no_conflict_act_to_gact: false,
+                no_conflict_gact_to_act: false,
+            },
+            event_log: Default::default(),
+            histogram: Default::default(),
+            sampling: Default::default(),
+            energy: Default::default(),
+        };
+        let sampled = SettingsFile {
+            sampling: crate::sampler::SamplingSettings { interval: 100 },
+            ..settings.clone()
+        };
+        let info = RunInfo::new(None, Some(settings));
+        assert_ne!(
+            info.config_hash,
+            RunInfo::new(None, Some(sampled)).config_hash
+        );
+        let recorded = serde_json::to_value(&info).unwrap();
+        assert!(recorded["settings"]["energy"]["dram_act"].is_number());
+    }
+
+    #[test]
+    fn test_with_run_info() {
+        let info = RunInfo::new(None, None);
+        let result = serde_json::json!({ "total": 1.5 });
+        let written = serde_json::to_value(WithRunInfo::new(info.clone(), &result)).unwrap();
+        assert_eq!(written["total"], 1.5);
+        assert_eq!(written["run_info"]["config_hash"], info.config_hash);
+    }
+}
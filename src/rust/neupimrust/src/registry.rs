@@ -0,0 +1,134 @@
+//! Name registries for config values that select one of several implementations.
+//!
+//! A config enum such as [`SchedulerType`](crate::global_config::SchedulerType) lists its
+//! variants once, in a table of [`Choice`]s giving the canonical name, accepted aliases and a
+//! short description. Parsing, serialization and error messages all go through that table, so
+//! plugging in a new scheduler policy or tensor layout means adding an enum variant and one
+//! table entry.
+
+use std::fmt;
+
+use schemars::schema::Schema;
+
+/// One selectable value of a registered enum.
+#[derive(Debug)]
+pub struct Choice<T: 'static> {
+    pub value: T,
+    /// The canonical name, used when writing configs.
+    pub name: &'static str,
+    /// Other accepted spellings.
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+}
+
+/// An enum selected by name through a registry of [`Choice`]s.
+pub trait Registered: Copy + PartialEq + 'static {
+    /// The config field the enum is used for, for error messages.
+    const KIND: &'static str;
+
+    fn registry() -> &'static [Choice<Self>];
+
+    /// Returns the canonical name of `self`.
+    fn name(self) -> &'static str {
+        Self::registry()
+            .iter()
+            .find(|choice| choice.value == self)
+            .map(|choice| choice.name)
+            .expect("every variant is registered")
+    }
+
+    /// Looks up a value by its name or one of its aliases, ignoring case.
+    fn from_name(name: &str) -> Result<Self, UnknownChoice> {
+        Self::registry()
+            .iter()
+            .find(|choice| {
+                std::iter::once(&choice.name)
+                    .chain(choice.aliases)
+                    .any(|candidate| candidate.eq_ignore_ascii_case(name))
+            })
+            .map(|choice| choice.value)
+            .ok_or_else(|| UnknownChoice {
+                kind: Self::KIND,
+                name: name.to_string(),
+                valid: Self::registry()
+                    .iter()
+                    .map(|choice| match choice.aliases {
+                        [] => choice.name.to_string(),
+                        aliases => format!("{} (alias: {})", choice.name, aliases.join(", ")),
+                    })
+                    .collect(),
+            })
+    }
+}
+
+/// A name that matches no registered choice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownChoice {
+    pub kind: &'static str,
+    pub name: String,
+    /// The valid choices, formatted for display.
+    pub valid: Vec<String>,
+}
+
+impl fmt::Display for UnknownChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown {} `{}`, valid choices are: {}",
+            self.kind,
+            self.name,
+            self.valid.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownChoice {}
+
+/// The JSON Schema of a registered enum: a string that is one of the names or aliases.
+pub fn registry_schema<T: Registered>() -> Schema {
+    let names: Vec<_> = T::registry()
+        .iter()
+        .flat_map(|choice| std::iter::once(&choice.name).chain(choice.aliases))
+        .collect();
+    let descriptions: Vec<_> = T::registry()
+        .iter()
+        .map(|choice| format!("`{}`: {}", choice.name, choice.description))
+        .collect();
+    serde_json::from_value(serde_json::json!({
+        "type": "string",
+        "enum": names,
+        "description": descriptions.join("; "),
+    }))
+    .expect("a valid schema")
+}
+
+/// Implements `TryFrom<String>` and `From<Self> for String` through the registry, for use with
+/// `#[serde(try_from = "String", into = "String")]`, and `JsonSchema` from the registered names.
+macro_rules! registered_serde {
+    ($ty:ty) => {
+        impl TryFrom<String> for $ty {
+            type Error = $crate::registry::UnknownChoice;
+
+            fn try_from(name: String) -> Result<Self, Self::Error> {
+                <$ty as $crate::registry::Registered>::from_name(&name)
+            }
+        }
+
+        impl From<$ty> for String {
+            fn from(value: $ty) -> String {
+                $crate::registry::Registered::name(value).to_string()
+            }
+        }
+
+        impl schemars::JsonSchema for $ty {
+            fn schema_name() -> String {
+                stringify!($ty).to_string()
+            }
+
+            fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+                $crate::registry::registry_schema::<$ty>()
+            }
+        }
+    };
+}
+pub(crate) use registered_serde;
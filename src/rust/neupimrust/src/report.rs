@@ -0,0 +1,452 @@
+//! Writing the counts report of a run.
+//!
+//! Reports go to an output directory: the one passed from C++, else `log_dir` of the config,
+//! else the working directory, so parallel runs with distinct `log_dir`s no longer overwrite
+//! each other. The report can be written as pretty or compact JSON, as TOML, or as CSV tables:
+//! `counts_summary.csv` with one row per tracked resource, and `counts_histograms.csv` with the
+//! busy/idle histograms in tidy form, one row per bucket.
+//!
+//! JSON and TOML reports carry a `schema_version` and can be read back with [`load_counts`], so
+//! other tools can analyze past runs through the query helpers of [`GlobalCountsCtx`].
+
+use std::{
+    ffi::c_char,
+    fmt::{self, Write as _},
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::error;
+
+use crate::{
+    config_loader::c_path, global_config::try_get_config, global_counts::GlobalCountsCtx,
+    provenance::RunInfo,
+};
+
+/// The file format of the counts report.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    JsonPretty,
+    JsonCompact,
+    Toml,
+    /// `<stem>_summary.csv` and `<stem>_histograms.csv`.
+    Csv,
+}
+
+/// The directory receiving the output files of a run: `dir` if given, else the `log_dir` of the
+/// global config if set, else the working directory.
+pub fn output_dir(dir: Option<PathBuf>) -> PathBuf {
+    dir.or_else(|| {
+        try_get_config()
+            .filter(|config| !config.log_dir.is_empty())
+            .map(|config| PathBuf::from(&config.log_dir))
+    })
+    .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The version of the counts report layout, increased on every incompatible change.
+pub const COUNTS_SCHEMA_VERSION: u32 = 1;
+
+/// The content of `counts.json`: the counts and the run that produced them.
+#[derive(Serialize)]
+pub struct CountsReport<'a> {
+    pub schema_version: u32,
+    pub run_info: RunInfo,
+    #[serde(flatten)]
+    pub counts: &'a GlobalCountsCtx,
+}
+
+impl<'a> CountsReport<'a> {
+    pub fn new(run_info: RunInfo, counts: &'a GlobalCountsCtx) -> Self {
+        CountsReport {
+            schema_version: COUNTS_SCHEMA_VERSION,
+            run_info,
+            counts,
+        }
+    }
+
+    /// Writes the report into `dir` as `<stem>.<ext>`, or as the CSV tables prefixed by `stem`,
+    /// and returns the written paths.
+    pub fn write(
+        &self,
+        dir: &Path,
+        stem: &str,
+        format: ReportFormat,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(dir)?;
+        let path = |suffix: &str| dir.join(format!("{stem}{suffix}"));
+        let paths = match format {
+            ReportFormat::JsonPretty => {
+                let path = path(".json");
+                serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), self)?;
+                vec![path]
+            }
+            ReportFormat::JsonCompact => {
+                let path = path(".json");
+                serde_json::to_writer(BufWriter::new(File::create(&path)?), self)?;
+                vec![path]
+            }
+            ReportFormat::Toml => {
+                let path = path(".toml");
+                std::fs::write(&path, self.to_toml()?)?;
+                vec![path]
+            }
+            ReportFormat::Csv => {
+                let (summary, histograms) = (path("_summary.csv"), path("_histograms.csv"));
+                std::fs::write(&summary, self.summary_csv())?;
+                std::fs::write(&histograms, self.histograms_csv())?;
+                vec![summary, histograms]
+            }
+        };
+        Ok(paths)
+    }
+
+    /// The report as TOML. It goes through JSON first, which turns the integer map keys into
+    /// strings and lets the `null`s of unset options be dropped, as TOML supports neither.
+    pub fn to_toml(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let mut value = serde_json::to_value(self)?;
+        remove_nulls(&mut value);
+        Ok(toml::to_string(&value)?)
+    }
+
+    /// One row per scope, unit and resource.
+    pub fn summary_csv(&self) -> String {
+        let mut csv = String::from(
+            "scope,unit,resource,total,busy_cycles,idle_cycles,busy_p50,busy_p90,busy_p99\n",
+        );
+        for (scope, unit, tracker) in self.counts.trackers() {
+            for (resource, activity) in tracker.iter() {
+                let percentile = |p| {
+                    let value = activity.busy_histo.percentile(p);
+                    value.map(|value| value.to_string()).unwrap_or_default()
+                };
+                writeln!(
+                    csv,
+                    "{scope},{},{resource:?},{},{},{},{},{},{}",
+                    unit.map(|unit| unit.to_string()).unwrap_or_default(),
+                    activity.total,
+                    activity.busy_cycles.0,
+                    activity.idle_cycles.0,
+                    percentile(50.0),
+                    percentile(90.0),
+                    percentile(99.0),
+                )
+                .unwrap();
+            }
+        }
+        csv
+    }
+
+    /// One row per histogram bucket: how many busy or idle periods lasted `cycles`, the first
+    /// length of the bucket.
+    pub fn histograms_csv(&self) -> String {
+        let mut csv = String::from("scope,unit,resource,state,cycles,count\n");
+        for (scope, unit, tracker) in self.counts.trackers() {
+            let unit = unit.map(|unit| unit.to_string()).unwrap_or_default();
+            for (resource, activity) in tracker.iter() {
+                for (state, histo) in [
+                    ("busy", &activity.busy_histo),
+                    ("idle", &activity.idle_histo),
+                ] {
+                    for (cycles, count) in histo.buckets() {
+                        writeln!(
+                            csv,
+                            "{scope},{unit},{resource:?},{state},{},{count}",
+                            cycles.0
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+        csv
+    }
+}
+
+/// A counts report read back by [`load_counts`].
+pub struct LoadedCounts {
+    pub schema_version: u32,
+    pub run_info: RunInfo,
+    pub counts: GlobalCountsCtx,
+}
+
+#[derive(Debug)]
+pub enum CountsError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file is not a counts report of the current layout.
+    Parse { path: PathBuf, message: String },
+    /// The report has another `schema_version`, or none if it predates the versioned layout.
+    UnsupportedSchemaVersion { path: PathBuf, version: Option<u32> },
+}
+
+impl fmt::Display for CountsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountsError::Io { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            CountsError::Parse { path, message } => {
+                write!(f, "{} is not a counts report: {message}", path.display())
+            }
+            CountsError::UnsupportedSchemaVersion {
+                path,
+                version: None,
+            } => write!(
+                f,
+                "{} has no schema_version, it predates the versioned counts report",
+                path.display()
+            ),
+            CountsError::UnsupportedSchemaVersion {
+                path,
+                version: Some(version),
+            } => write!(
+                f,
+                "{} has schema version {version}, expected {COUNTS_SCHEMA_VERSION}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CountsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CountsError::Io { source, .. } => Some(source),
+            CountsError::Parse { .. } | CountsError::UnsupportedSchemaVersion { .. } => None,
+        }
+    }
+}
+
+/// Reads a counts report written as JSON or, with a `.toml` extension, as TOML.
+///
+/// The `out_dir` of the counts is the directory of the report. If the event stream is no longer
+/// at its recorded path, it is looked up next to the report.
+pub fn load_counts(path: &Path) -> Result<LoadedCounts, CountsError> {
+    let parse_error = |message: String| CountsError::Parse {
+        path: path.to_owned(),
+        message,
+    };
+    let text = std::fs::read_to_string(path).map_err(|source| CountsError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    // both formats go through JSON, whose map keys deserialize into integers
+    let mut value: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|err| parse_error(err.to_string()))?,
+        _ => serde_json::from_str(&text).map_err(|err| parse_error(err.to_string()))?,
+    };
+    let Value::Object(map) = &mut value else {
+        return Err(parse_error("expected a table at the top level".to_string()));
+    };
+    let schema_version = map
+        .remove("schema_version")
+        .map(serde_json::from_value::<u32>)
+        .transpose()
+        .map_err(|err| parse_error(format!("schema_version: {err}")))?;
+    if schema_version != Some(COUNTS_SCHEMA_VERSION) {
+        return Err(CountsError::UnsupportedSchemaVersion {
+            path: path.to_owned(),
+            version: schema_version,
+        });
+    }
+    let run_info = map
+        .remove("run_info")
+        .ok_or_else(|| parse_error("no run_info".to_string()))?;
+    let run_info =
+        serde_json::from_value(run_info).map_err(|err| parse_error(format!("run_info: {err}")))?;
+    let mut counts: GlobalCountsCtx =
+        serde_json::from_value(value).map_err(|err| parse_error(err.to_string()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    counts.events.relocate(dir);
+    counts.out_dir = dir.to_path_buf();
+    Ok(LoadedCounts {
+        schema_version: COUNTS_SCHEMA_VERSION,
+        run_info,
+        counts,
+    })
+}
+
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// 保存累计的数据到输出目录，并将事件流写入磁盘
+///
+/// # 参数
+///
+/// * `dir` - 输出目录，为空时使用 [`GlobalCountsCtx::out_dir`]
+/// * `stem` - 不带扩展名的文件名，为空时使用`counts`
+/// * `format` - 文件格式
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+#[no_mangle]
+pub extern "C" fn save_global_counts_as(
+    ctx: &mut GlobalCountsCtx,
+    dir: *const c_char,
+    stem: *const c_char,
+    format: ReportFormat,
+) -> bool {
+    if let Err(err) = ctx.events.flush() {
+        error!("无法写入事件流: {err}");
+    }
+    let dir = c_path(dir).unwrap_or_else(|| ctx.out_dir.clone());
+    let stem = c_path(stem).map_or("counts".to_string(), |stem| stem.display().to_string());
+    let report = CountsReport::new(RunInfo::current(), ctx);
+    match report.write(&dir, &stem, format) {
+        Ok(_) => true,
+        Err(err) => {
+            error!("无法写入{}: {err}", dir.join(stem).display());
+            false
+        }
+    }
+}
+
+/// 以格式化的 JSON 保存累计的数据到输出目录下的 `counts.json`
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+#[no_mangle]
+pub extern "C" fn save_global_counts_to_file(ctx: &mut GlobalCountsCtx) -> bool {
+    save_global_counts_as(
+        ctx,
+        std::ptr::null(),
+        std::ptr::null(),
+        ReportFormat::JsonPretty,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::EventLog,
+        global_counts::{add_core_ops, reduce_core_ops, update_last_cycle, Cycle, MemOp},
+    };
+
+    fn counts() -> GlobalCountsCtx {
+        let mut ctx = GlobalCountsCtx::default();
+        ctx.register_units(1, 1);
+        add_core_ops(&mut ctx, 0, MemOp::Load, 1, 10);
+        assert!(reduce_core_ops(&mut ctx, 0, MemOp::Load, 1, 14));
+        ctx
+    }
+
+    #[test]
+    fn test_csv_tables() {
+        let ctx = counts();
+        let report = CountsReport::new(RunInfo::new(None, None), &ctx);
+        let summary = report.summary_csv();
+        assert!(summary.contains("\nall,,Load,1,4,10,4,4,4\n"));
+        assert!(summary.contains("\ncore,0,LoadOrStore,1,4,10,4,4,4\n"));
+        assert!(summary.contains("\nchannel,0,Store,0,0,0,,,\n"));
+        let histograms = report.histograms_csv();
+        assert!(histograms.contains("\ncore,0,Load,busy,4,1\n"));
+        assert!(histograms.contains("\nall,,Load,idle,10,1\n"));
+    }
+
+    #[test]
+    fn test_write_formats() {
+        let mut ctx = counts();
+        let dir = crate::test_dir("write_formats");
+        let report = CountsReport::new(RunInfo::new(None, None), &ctx);
+        for format in [
+            ReportFormat::JsonPretty,
+            ReportFormat::JsonCompact,
+            ReportFormat::Toml,
+            ReportFormat::Csv,
+        ] {
+            for path in report.write(&dir, "counts", format).unwrap() {
+                assert!(path.starts_with(&dir));
+            }
+        }
+        let toml: toml::Table = toml::from_str(&report.to_toml().unwrap()).unwrap();
+        assert_eq!(
+            toml["cores"]["0"]["Load"]["busy_cycles"].as_integer(),
+            Some(4)
+        );
+
+        ctx.out_dir = dir.join("out");
+        save_global_counts_to_file(&mut ctx);
+        assert!(dir.join("out/counts.json").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_counts() {
+        let dir = crate::test_dir("load_counts");
+        let mut ctx = counts();
+        ctx.events = EventLog::to_file(dir.join("events.jsonl"), Default::default()).unwrap();
+        add_core_ops(&mut ctx, 0, MemOp::Store, 1, 20);
+        assert!(reduce_core_ops(&mut ctx, 0, MemOp::Store, 1, 30));
+        update_last_cycle(&mut ctx, 40);
+        ctx.out_dir = dir.clone();
+        assert!(save_global_counts_as(
+            &mut ctx,
+            std::ptr::null(),
+            std::ptr::null(),
+            ReportFormat::JsonPretty
+        ));
+        assert!(save_global_counts_as(
+            &mut ctx,
+            std::ptr::null(),
+            std::ptr::null(),
+            ReportFormat::Toml
+        ));
+
+        for path in [dir.join("counts.json"), dir.join("counts.toml")] {
+            let loaded = load_counts(&path).unwrap().counts;
+            assert_eq!(loaded.busy_ratio(MemOp::Load), Some(4.0 / 14.0));
+            assert_eq!(
+                loaded.busy_ratio(MemOp::Store),
+                ctx.busy_ratio(MemOp::Store)
+            );
+            let store = loaded.cores[&0].get(MemOp::Store).unwrap();
+            assert_eq!(store.busy_histo.buckets()[&Cycle(10)], 1);
+            assert_eq!(loaded.stage_breakdown()[0].counts.cycles, 40);
+            let events = loaded.events_in_window(15, 30).unwrap();
+            assert_eq!(events.len(), 2);
+            assert_eq!(events[0].cycle, 20);
+        }
+
+        std::fs::write(dir.join("old.json"), r#"{"run_info": {}}"#).unwrap();
+        assert!(matches!(
+            load_counts(&dir.join("old.json")),
+            Err(CountsError::UnsupportedSchemaVersion { version: None, .. })
+        ));
+        std::fs::write(dir.join("newer.json"), r#"{"schema_version": 99}"#).unwrap();
+        assert!(matches!(
+            load_counts(&dir.join("newer.json")),
+            Err(CountsError::UnsupportedSchemaVersion {
+                version: Some(99),
+                ..
+            })
+        ));
+        std::fs::write(dir.join("broken.json"), "[1, 2]").unwrap();
+        assert!(matches!(
+            load_counts(&dir.join("broken.json")),
+            Err(CountsError::Parse { .. })
+        ));
+        assert!(matches!(
+            load_counts(&dir.join("missing.json")),
+            Err(CountsError::Io { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,399 @@
+//! Roofline analysis of the NPU per stage and per operation.
+//!
+//! The work of a stage or an operation is `2 ×` its systolic array MACs in FLOPs, and its traffic
+//! the DRAM reads and writes of `dram_req_size` bytes it issued. Against the peak compute
+//! `2 × core_width × core_height × core_freq × num_cores` and the peak bandwidth
+//! `dram_channels × dram_freq × dram_req_size` of the [`SimulationConfig`], this places each of
+//! them on the roofline: its arithmetic intensity, the performance it attained, and whether the
+//! roof above it is the memory or the compute one. An operation runs on several cores at once, so
+//! its time is the core cycles of its tiles divided by the number of cores. PIM MACs happen
+//! inside the DRAM, behind the bandwidth roof, and are left out.
+//!
+//! The report is written as `roofline.csv`, one row per point, and `roofline.svg`, a log-log
+//! chart of the roofs and the points. Both start with a comment naming the [`RunInfo`] of the
+//! run.
+
+use std::{fmt::Write as _, path::Path};
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::{
+    global_config::{try_get_config, SimulationConfig},
+    global_counts::{GlobalCountsCtx, HwCounts, HwOp},
+    provenance::RunInfo,
+};
+
+/// Which roof limits a point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Bound {
+    Memory,
+    Compute,
+}
+
+/// The peaks of the simulated hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Roofline {
+    /// In FLOP/s.
+    pub peak_flops: f64,
+    /// In bytes/s.
+    pub peak_bandwidth: f64,
+    /// In Hz.
+    pub core_freq: f64,
+    pub num_cores: u32,
+    pub dram_req_size: u32,
+}
+
+impl Roofline {
+    pub fn new(config: &SimulationConfig) -> Self {
+        let core_freq = f64::from(config.core_freq) * 1e6;
+        Roofline {
+            peak_flops: 2.0
+                * f64::from(config.core_width)
+                * f64::from(config.core_height)
+                * core_freq
+                * f64::from(config.num_cores),
+            peak_bandwidth: f64::from(config.dram_channels)
+                * f64::from(config.dram_freq)
+                * 1e6
+                * f64::from(config.dram_req_size),
+            core_freq,
+            num_cores: config.num_cores,
+            dram_req_size: config.dram_req_size,
+        }
+    }
+
+    /// The arithmetic intensity where the memory roof meets the compute roof, in FLOP/byte.
+    pub fn ridge_point(&self) -> f64 {
+        self.peak_flops / self.peak_bandwidth
+    }
+
+    /// The highest performance reachable at `intensity`, in FLOP/s.
+    pub fn roof(&self, intensity: f64) -> f64 {
+        self.peak_flops.min(intensity * self.peak_bandwidth)
+    }
+
+    /// Places `counts` on the roofline, taking `cycles` core cycles of wall time.
+    fn point(&self, scope: &'static str, name: String, counts: &HwCounts, cycles: f64) -> Point {
+        let flops = 2 * counts.get(HwOp::SystolicMac);
+        let bytes = (counts.get(HwOp::DramRead) + counts.get(HwOp::DramWrite))
+            * u64::from(self.dram_req_size);
+        let intensity = (bytes > 0).then(|| flops as f64 / bytes as f64);
+        let seconds = cycles / self.core_freq;
+        let attained = if seconds > 0.0 {
+            flops as f64 / seconds
+        } else {
+            0.0
+        };
+        let roof = intensity.map(|intensity| self.roof(intensity));
+        Point {
+            scope,
+            name,
+            flops,
+            bytes,
+            cycles: cycles.round() as u64,
+            arithmetic_intensity: intensity,
+            attained,
+            roof,
+            bound: intensity.map(|intensity| {
+                if intensity < self.ridge_point() {
+                    Bound::Memory
+                } else {
+                    Bound::Compute
+                }
+            }),
+            efficiency: roof.filter(|&roof| roof > 0.0).map(|roof| attained / roof),
+        }
+    }
+}
+
+/// A stage or an operation on the roofline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Point {
+    /// `stage` or `operation`.
+    pub scope: &'static str,
+    pub name: String,
+    pub flops: u64,
+    pub bytes: u64,
+    /// Wall time in core cycles.
+    pub cycles: u64,
+    /// In FLOP/byte, `None` without DRAM traffic.
+    pub arithmetic_intensity: Option<f64>,
+    /// In FLOP/s.
+    pub attained: f64,
+    /// The roof above the point, in FLOP/s.
+    pub roof: Option<f64>,
+    pub bound: Option<Bound>,
+    /// The share of the roof attained.
+    pub efficiency: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RooflineReport {
+    pub roofline: Roofline,
+    pub points: Vec<Point>,
+}
+
+impl RooflineReport {
+    pub fn new(roofline: Roofline, ctx: &GlobalCountsCtx) -> Self {
+        let stages = ctx.stages.iter().map(|(stage, counts)| {
+            roofline.point("stage", format!("{stage:?}"), counts, counts.cycles as f64)
+        });
+        let cores = f64::from(roofline.num_cores.max(1));
+        let operations = ctx.operations.iter().map(|(optype, counts)| {
+            roofline.point(
+                "operation",
+                optype.clone(),
+                counts,
+                counts.cycles as f64 / cores,
+            )
+        });
+        RooflineReport {
+            roofline,
+            points: stages.chain(operations).collect(),
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "scope,name,flops,bytes,cycles,arithmetic_intensity,attained_flops,roof_flops,bound,efficiency\n",
+        );
+        let optional = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+        for point in &self.points {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{}",
+                point.scope,
+                point.name,
+                point.flops,
+                point.bytes,
+                point.cycles,
+                optional(point.arithmetic_intensity),
+                point.attained,
+                optional(point.roof),
+                point
+                    .bound
+                    .map(|bound| format!("{bound:?}"))
+                    .unwrap_or_default(),
+                optional(point.efficiency),
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// A log-log chart of the roofs and of the points that did any work and moved any data.
+    pub fn to_svg(&self) -> String {
+        const WIDTH: f64 = 720.0;
+        const HEIGHT: f64 = 480.0;
+        const LEFT: f64 = 70.0;
+        const RIGHT: f64 = 20.0;
+        const TOP: f64 = 20.0;
+        const BOTTOM: f64 = 50.0;
+
+        let roofline = &self.roofline;
+        let ridge = roofline.ridge_point();
+        let points: Vec<(&Point, f64)> = self
+            .points
+            .iter()
+            .filter_map(|point| Some((point, point.arithmetic_intensity?)))
+            .filter(|&(point, intensity)| intensity > 0.0 && point.attained > 0.0)
+            .collect();
+        // decades covering the points and two decades around the ridge point
+        let decades = |values: &mut dyn Iterator<Item = f64>| {
+            let (min, max) = values.fold((f64::MAX, f64::MIN), |(min, max), value| {
+                (min.min(value), max.max(value))
+            });
+            (
+                min.log10().floor(),
+                max.log10().ceil().max(min.log10().floor() + 1.0),
+            )
+        };
+        let (x_min, x_max) = decades(
+            &mut [ridge / 100.0, ridge * 100.0]
+                .into_iter()
+                .chain(points.iter().map(|&(_, intensity)| intensity)),
+        );
+        let (y_min, y_max) = decades(
+            &mut [roofline.roof(10f64.powf(x_min)), roofline.peak_flops]
+                .into_iter()
+                .chain(points.iter().map(|(point, _)| point.attained)),
+        );
+        let x =
+            |value: f64| LEFT + (value.log10() - x_min) / (x_max - x_min) * (WIDTH - LEFT - RIGHT);
+        let y = |value: f64| {
+            HEIGHT - BOTTOM - (value.log10() - y_min) / (y_max - y_min) * (HEIGHT - TOP - BOTTOM)
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+             font-family=\"sans-serif\" font-size=\"11\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n"
+        );
+        for decade in x_min as i32..=x_max as i32 {
+            let px = x(10f64.powi(decade));
+            writeln!(
+                svg,
+                "<line x1=\"{px:.1}\" y1=\"{TOP}\" x2=\"{px:.1}\" y2=\"{:.1}\" stroke=\"#ddd\"/>\
+                 <text x=\"{px:.1}\" y=\"{:.1}\" text-anchor=\"middle\">1e{decade}</text>",
+                HEIGHT - BOTTOM,
+                HEIGHT - BOTTOM + 15.0
+            )
+            .unwrap();
+        }
+        for decade in y_min as i32..=y_max as i32 {
+            let py = y(10f64.powi(decade));
+            writeln!(
+                svg,
+                "<line x1=\"{LEFT}\" y1=\"{py:.1}\" x2=\"{:.1}\" y2=\"{py:.1}\" stroke=\"#ddd\"/>\
+                 <text x=\"{:.1}\" y=\"{py:.1}\" text-anchor=\"end\" dy=\"4\">1e{decade}</text>",
+                WIDTH - RIGHT,
+                LEFT - 5.0
+            )
+            .unwrap();
+        }
+        writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">arithmetic intensity (FLOP/byte)</text>\n\
+             <text transform=\"translate(15 {:.1}) rotate(-90)\" text-anchor=\"middle\">performance (FLOP/s)</text>",
+            (LEFT + WIDTH - RIGHT) / 2.0,
+            HEIGHT - 10.0,
+            (TOP + HEIGHT - BOTTOM) / 2.0
+        )
+        .unwrap();
+
+        let x_start = 10f64.powf(x_min);
+        let x_end = 10f64.powf(x_max);
+        writeln!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"black\" stroke-width=\"2\" \
+             points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"/>",
+            x(x_start),
+            y(roofline.roof(x_start)),
+            x(ridge),
+            y(roofline.peak_flops),
+            x(x_end),
+            y(roofline.peak_flops)
+        )
+        .unwrap();
+        for (point, intensity) in points {
+            let color = if point.scope == "stage" {
+                "#1f77b4"
+            } else {
+                "#ff7f0e"
+            };
+            let (px, py) = (x(intensity), y(point.attained));
+            writeln!(
+                svg,
+                "<circle cx=\"{px:.1}\" cy=\"{py:.1}\" r=\"4\" fill=\"{color}\"/>\
+                 <text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                px + 6.0,
+                py - 6.0,
+                escape_xml(&point.name)
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Writes `roofline.csv` and `roofline.svg` into `dir`, each after a comment naming
+    /// `run_info`.
+    pub fn write(&self, dir: &Path, run_info: &RunInfo) -> std::io::Result<()> {
+        std::fs::write(
+            dir.join("roofline.csv"),
+            format!("# {run_info}\n{}", self.to_csv()),
+        )?;
+        std::fs::write(
+            dir.join("roofline.svg"),
+            format!("<!-- {run_info} -->\n{}", self.to_svg()),
+        )
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// 对每个阶段和每种算子做 roofline 分析，保存到输出目录下的 `roofline.csv` 和 `roofline.svg`
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`；配置未初始化时不写入，返回`false`
+#[no_mangle]
+pub extern "C" fn save_roofline_report(ctx: &mut GlobalCountsCtx) -> bool {
+    let Some(config) = try_get_config() else {
+        error!("配置未初始化，无法生成 roofline 报告");
+        return false;
+    };
+    let report = RooflineReport::new(Roofline::new(config), ctx);
+    match report.write(&ctx.out_dir, &RunInfo::current()) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("无法写入 roofline 报告到{}: {err}", ctx.out_dir.display());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_counts::{add_hw_ops, add_operation_cycles, update_last_cycle};
+
+    #[test]
+    fn test_roofline() {
+        let config = SimulationConfig {
+            num_cores: 2,
+            core_freq: 1000,
+            core_width: 8,
+            core_height: 8,
+            dram_channels: 4,
+            dram_freq: 1000,
+            dram_req_size: 32,
+            ..Default::default()
+        };
+        let roofline = Roofline::new(&config);
+        assert_eq!(roofline.peak_flops, 256e9);
+        assert_eq!(roofline.peak_bandwidth, 128e9);
+        assert_eq!(roofline.ridge_point(), 2.0);
+
+        let mut ctx = GlobalCountsCtx::default();
+        let gemm = c"MatMul".as_ptr();
+        add_hw_ops(&mut ctx, HwOp::SystolicMac, 64_000, gemm);
+        add_hw_ops(&mut ctx, HwOp::DramRead, 1_000, gemm);
+        add_operation_cycles(&mut ctx, gemm, 2_000);
+        update_last_cycle(&mut ctx, 1_000);
+
+        let report = RooflineReport::new(roofline, &ctx);
+        let stage = &report.points[0];
+        assert_eq!(
+            (stage.flops, stage.bytes, stage.cycles),
+            (128_000, 32_000, 1_000)
+        );
+        assert_eq!(stage.arithmetic_intensity, Some(4.0));
+        assert_eq!(stage.bound, Some(Bound::Compute));
+        assert_eq!(stage.attained, 128e9);
+        assert_eq!(stage.efficiency, Some(0.5));
+        // 2000 core cycles of tiles over 2 cores
+        let operation = &report.points[1];
+        assert_eq!((operation.scope, operation.cycles), ("operation", 1_000));
+        assert!(report
+            .to_csv()
+            .contains("\nstage,A,128000,32000,1000,4,128000000000,256000000000,Compute,0.5\n"));
+        let svg = report.to_svg();
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert!(svg.contains(">MatMul</text>"));
+
+        let dir = crate::test_dir("roofline");
+        let run_info = RunInfo::new(Some(config), None);
+        report.write(&dir, &run_info).unwrap();
+        let csv = std::fs::read_to_string(dir.join("roofline.csv")).unwrap();
+        assert_eq!(csv.lines().next(), Some(format!("# {run_info}").as_str()));
+        let svg = std::fs::read_to_string(dir.join("roofline.svg")).unwrap();
+        assert!(svg.starts_with(&format!("<!-- {run_info} -->\n<svg")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+//! Time-series sampling of the counters.
+//!
+//! The counts report only holds end-of-run totals. A [`Sampler`] snapshots every tracker of the
+//! [`GlobalCountsCtx`] every `interval` core cycles, driven by `update_last_cycle`, and writes the
+//! snapshots as a tidy table to `timeseries.csv`: one row per sample, scope, unit and resource.
+//! A sample is taken at the first cycle the simulator reports at or after it is due, so a late
+//! sample can cover more than one interval. `started` is the number of operations started since
+//! the previous sample and `elapsed` the cycles since then, so `started / elapsed` is the
+//! bandwidth between the two samples. The first line of the file is a `#` comment naming the
+//! [`RunInfo`] of the run.
+
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    activity::MemStatus,
+    global_counts::{GlobalCountsCtx, MemOp, RunStage},
+    provenance::RunInfo,
+};
+
+/// The `[sampling]` table of the settings file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingSettings {
+    /// Sample the counters every `interval` core cycles, 0 disables sampling.
+    pub interval: u64,
+}
+
+/// The state of one resource at a sampled cycle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sample {
+    pub cycle: u64,
+    /// Cycles since the previous sample, 0 for the first one.
+    pub elapsed: u64,
+    pub stage: RunStage,
+    pub scope: &'static str,
+    pub unit: Option<u32>,
+    pub resource: MemOp,
+    /// Ongoing operations.
+    pub current: u64,
+    /// Operations started since the beginning of the run.
+    pub total: u64,
+    /// Operations started since the previous sample.
+    pub started: u64,
+    pub busy: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sampler {
+    interval: u64,
+    /// The next cycle to sample.
+    next: u64,
+    samples: Vec<Sample>,
+    /// The cycle of the previous sample.
+    last_cycle: Option<u64>,
+    /// The totals of the previous sample, by scope, unit and resource.
+    last_totals: BTreeMap<(&'static str, Option<u32>, MemOp), u64>,
+}
+
+impl Sampler {
+    /// A sampler taking a sample every `interval` cycles, or none if `interval` is 0.
+    pub fn new(interval: u64) -> Self {
+        Sampler {
+            interval,
+            ..Default::default()
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Whether a sample is due at `cycle`.
+    pub fn is_due(&self, cycle: u64) -> bool {
+        self.interval != 0 && cycle >= self.next
+    }
+
+    /// Samples every tracker of `ctx` at `cycle` and schedules the next sample on the following
+    /// multiple of the interval.
+    pub fn sample(&mut self, cycle: u64, ctx: &GlobalCountsCtx) {
+        let elapsed = self.last_cycle.map_or(0, |last| cycle - last);
+        for (scope, unit, tracker) in ctx.trackers() {
+            for (resource, activity) in tracker.iter() {
+                let last_total = self
+                    .last_totals
+                    .insert((scope, unit, resource), activity.total)
+                    .unwrap_or(0);
+                self.samples.push(Sample {
+                    cycle,
+                    elapsed,
+                    stage: ctx.current_stage,
+                    scope,
+                    unit,
+                    resource,
+                    current: activity.current,
+                    total: activity.total,
+                    started: activity.total - last_total,
+                    busy: matches!(activity.status, MemStatus::Busy(_)),
+                });
+            }
+        }
+        self.last_cycle = Some(cycle);
+        self.next = (cycle / self.interval.max(1) + 1) * self.interval;
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("cycle,elapsed,stage,scope,unit,resource,current,total,started,busy\n");
+        for sample in &self.samples {
+            writeln!(
+                csv,
+                "{},{},{:?},{},{},{:?},{},{},{},{}",
+                sample.cycle,
+                sample.elapsed,
+                sample.stage,
+                sample.scope,
+                sample.unit.map(|unit| unit.to_string()).unwrap_or_default(),
+                sample.resource,
+                sample.current,
+                sample.total,
+                sample.started,
+                u8::from(sample.busy),
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// Writes the samples as CSV, after a comment line naming `run_info`.
+    pub fn write_to_file(&self, path: &Path, run_info: &RunInfo) -> std::io::Result<()> {
+        std::fs::write(path, format!("# {run_info}\n{}", self.to_csv()))
+    }
+}
+
+/// 保存采样得到的时间序列到输出目录下的 `timeseries.csv`，未开启采样时不写入
+///
+/// # 返回值
+///
+/// 如果写入成功或未开启采样，返回`true`
+#[no_mangle]
+pub extern "C" fn save_timeseries_to_file(ctx: &mut GlobalCountsCtx) -> bool {
+    if ctx.sampler.interval() == 0 {
+        return true;
+    }
+    let path = ctx.out_dir.join("timeseries.csv");
+    match ctx.sampler.write_to_file(&path, &RunInfo::current()) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("无法写入{}: {err}", path.display());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_counts::{add_core_ops, reduce_core_ops, update_last_cycle};
+
+    #[test]
+    fn test_sampling() {
+        let mut ctx = GlobalCountsCtx {
+            sampler: Sampler::new(10),
+            ..Default::default()
+        };
+        ctx.register_units(1, 0);
+        for cycle in 0..25 {
+            if cycle == 3 || cycle == 12 {
+                add_core_ops(&mut ctx, 0, MemOp::Load, 2, cycle);
+            }
+            if cycle == 15 {
+                assert!(reduce_core_ops(&mut ctx, 0, MemOp::Load, 4, cycle));
+            }
+            update_last_cycle(&mut ctx, cycle);
+        }
+        let loads: Vec<_> = ctx
+            .sampler
+            .samples()
+            .iter()
+            .filter(|s| s.scope == "all" && s.resource == MemOp::Load)
+            .map(|s| (s.cycle, s.current, s.total, s.started, s.busy))
+            .collect();
+        assert_eq!(
+            loads,
+            vec![
+                (0, 0, 0, 0, false),
+                (10, 2, 2, 2, true),
+                (20, 0, 4, 2, false)
+            ]
+        );
+        let csv = ctx.sampler.to_csv();
+        assert!(csv.contains("\n10,10,A,core,0,Load,2,2,2,1\n"));
+        assert!(csv.contains("\n20,10,A,all,,LoadOrStore,0,1,0,0\n"));
+
+        let dir = crate::test_dir("sampling");
+        let run_info = RunInfo::new(None, None);
+        let path = dir.join("timeseries.csv");
+        ctx.sampler.write_to_file(&path, &run_info).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written, format!("# {run_info}\n{csv}"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_late_samples_record_elapsed_cycles() {
+        let mut ctx = GlobalCountsCtx {
+            sampler: Sampler::new(10),
+            ..Default::default()
+        };
+        ctx.register_units(1, 0);
+        // the simulator only reports some cycles, so the sample due at 10 is taken at 33
+        for cycle in [0, 4, 33, 41] {
+            add_core_ops(&mut ctx, 0, MemOp::Load, 1, cycle);
+            update_last_cycle(&mut ctx, cycle);
+        }
+        let loads: Vec<_> = ctx
+            .sampler
+            .samples()
+            .iter()
+            .filter(|s| s.scope == "all" && s.resource == MemOp::Load)
+            .map(|s| (s.cycle, s.elapsed, s.started))
+            .collect();
+        assert_eq!(loads, vec![(0, 0, 1), (33, 33, 2), (41, 8, 1)]);
+    }
+}
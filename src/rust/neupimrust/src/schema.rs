@@ -0,0 +1,132 @@
+//! JSON Schemas of the config files.
+//!
+//! The schemas are derived from the serde definitions of the config structs, so they cannot
+//! drift from what the loader accepts. Copies for editors live in `configs/schemas`; a test
+//! checks that they are current, and `neupimsim schema --out configs/schemas` refreshes them.
+
+use std::path::{Path, PathBuf};
+
+use schemars::{
+    schema::{Metadata, RootSchema, Schema, SchemaObject},
+    schema_for,
+};
+
+use crate::{
+    config_loader::{COMMENT_PREFIX, LEGACY_KEYS, UNUSED_KEYS},
+    global_config::SimulationConfig,
+    settings::SettingsFile,
+    sweep::SweepSpec,
+};
+
+/// The schema of every config file format, by name.
+pub fn config_schemas() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        ("simulation_config", simulation_config_schema()),
+        ("settings", schema_for!(SettingsFile)),
+        ("sweep_spec", schema_for!(SweepSpec)),
+    ]
+}
+
+/// The schema of one of the split config files.
+///
+/// A single file only holds some of the fields, so none is listed as required; the loader
+/// reports missing fields once all files are merged. The legacy and unused keys are listed as
+/// deprecated properties and comment keys are matched by a pattern, so older files still pass
+/// despite `additionalProperties: false`.
+fn simulation_config_schema() -> RootSchema {
+    let described = |description: String, deprecated: bool| {
+        Schema::Object(SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(description),
+                deprecated,
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    };
+    let mut schema = schema_for!(SimulationConfig);
+    let object = schema.schema.object();
+    object.required.clear();
+    for (legacy, field, multiplier) in LEGACY_KEYS {
+        let unit = match multiplier {
+            1 => String::new(),
+            multiplier => format!(", a plain number is multiplied by {multiplier}"),
+        };
+        object.properties.insert(
+            legacy.to_string(),
+            described(format!("Legacy spelling of `{field}`{unit}"), true),
+        );
+    }
+    for key in UNUSED_KEYS {
+        object.properties.insert(
+            key.to_string(),
+            described("Not used by the simulator, ignored".to_string(), true),
+        );
+    }
+    object.pattern_properties.insert(
+        format!("^{COMMENT_PREFIX}"),
+        described("A comment, ignored".to_string(), false),
+    );
+    schema
+}
+
+/// The schema serialized as it is written to disk.
+pub fn schema_to_string(schema: &RootSchema) -> String {
+    serde_json::to_string_pretty(schema).expect("schemas are serializable") + "\n"
+}
+
+/// Writes `<name>.schema.json` for every schema into `dir` and returns the written paths.
+pub fn write_schemas(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    config_schemas()
+        .into_iter()
+        .map(|(name, schema)| {
+            let path = dir.join(format!("{name}.schema.json"));
+            std::fs::write(&path, schema_to_string(&schema))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_committed_schemas_are_current() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../configs/schemas");
+        for (name, schema) in config_schemas() {
+            let path = dir.join(format!("{name}.schema.json"));
+            let committed = std::fs::read_to_string(&path).unwrap_or_default();
+            assert!(
+                committed == schema_to_string(&schema),
+                "{} is out of date, run `neupimsim schema --out configs/schemas`",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_lists_aliases_and_units() {
+        let schema = serde_json::to_value(schema_for!(SimulationConfig)).unwrap();
+        let run_mode = &schema["definitions"]["RunMode"]["enum"];
+        assert!(run_mode.as_array().unwrap().contains(&"npu_pim".into()));
+        let hbm_size = &schema["properties"]["hbm_size"];
+        assert_eq!(hbm_size["x-unit"], "B");
+        assert_eq!(hbm_size["description"], "HBM size, in bytes");
+        assert_eq!(schema["additionalProperties"], false);
+    }
+
+    #[test]
+    fn test_schema_accepts_split_files_and_legacy_keys() {
+        let schema = serde_json::to_value(simulation_config_schema()).unwrap();
+        assert!(schema.get("required").is_none());
+        assert_eq!(schema["properties"]["HBM_size"]["deprecated"], true);
+        assert_eq!(
+            schema["properties"]["systolic_array_count"]["deprecated"],
+            true
+        );
+        assert!(schema["patternProperties"]["^__"].is_object());
+        assert_eq!(schema["additionalProperties"], false);
+    }
+}
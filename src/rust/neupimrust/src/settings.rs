@@ -1,53 +1,140 @@
 //! This module provides functionality to manage application settings.
 //! It includes functions to initialize settings from a file and retrieve them.
 
-use std::{ffi::c_char, sync::Mutex};
-use tracing::info;
+use std::{ffi::c_char, path::Path, sync::Mutex};
+use tracing::{error, info};
 
-/// A global mutex-protected optional `Settings` instance.
-pub static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+use crate::{
+    config_loader::{c_path, ConfigError},
+    energy::EnergyTable,
+    event_log::EventFilter,
+    histogram::HistogramStrategy,
+    sampler::SamplingSettings,
+};
+
+/// A global mutex-protected optional settings file; the getters below read its tables.
+pub static SETTINGS: Mutex<Option<SettingsFile>> = Mutex::new(None);
 
 /// A struct representing the application settings.
 #[repr(C)]
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 pub struct Settings {
+    /// Complete PIM reads immediately instead of simulating them in DRAM.
     pub fast_read: bool,
+    /// Skip interconnect timing.
     pub fast_icnt: bool,
+    /// Ignore bank conflicts from ACT to a following PIM GACT.
     pub no_conflict_act_to_gact: bool,
+    /// Ignore bank conflicts from PIM GACT to a following ACT.
     pub no_conflict_gact_to_act: bool,
 }
 
+/// The contents of a settings file: the [`Settings`] shared with C++ and the tables only read on
+/// the Rust side.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct SettingsFile {
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Which events are written to `events.jsonl`.
+    #[serde(default)]
+    pub event_log: EventFilter,
+    /// How busy and idle period lengths are bucketed in the counts report.
+    #[serde(default)]
+    pub histogram: HistogramStrategy,
+    /// How often the counters are sampled into `timeseries.csv`.
+    #[serde(default)]
+    pub sampling: SamplingSettings,
+    /// The energy of each operation, for the energy report.
+    #[serde(default)]
+    pub energy: EnergyTable,
+}
+
 /// Initializes the settings from a file specified by a C-style string path.
 ///
+/// Returns `false` and logs the reason if the file cannot be read or parsed.
+///
 /// # Safety
 ///
-/// This function is unsafe because it dereferences a raw pointer.
+/// `file_path` must be null or point to a valid nul-terminated string.
 #[no_mangle]
-pub extern "C" fn init_settings_with_file(file_path: *const c_char) {
-    let file_path = unsafe { std::ffi::CStr::from_ptr(file_path) };
-    let file_path = file_path.to_str().unwrap();
-    init_settings_with_file_(file_path);
+pub extern "C" fn init_settings_with_file(file_path: *const c_char) -> bool {
+    let Some(file_path) = c_path(file_path) else {
+        error!("no settings file given");
+        return false;
+    };
+    match init_settings_with_file_(&file_path) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("failed to load settings: {err}");
+            false
+        }
+    }
 }
 
 /// Helper function to initialize settings from a file path.
-fn init_settings_with_file_(file_path: &str) {
-    let settings = std::fs::read_to_string(file_path).unwrap();
-    let settings = toml::from_str(&settings).unwrap();
-    set_settings(settings);
+pub(crate) fn init_settings_with_file_(file_path: &Path) -> Result<(), ConfigError> {
+    let content = std::fs::read_to_string(file_path).map_err(|source| ConfigError::Io {
+        path: file_path.to_owned(),
+        source,
+    })?;
+    let file = toml::from_str(&content).map_err(|source| ConfigError::Toml {
+        path: file_path.to_owned(),
+        source,
+    })?;
+    set_settings(file);
+    Ok(())
 }
 
 /// Initializes the settings using a default file path ("sjq.toml").
+///
+/// Returns `false` and logs the reason if the file cannot be read or parsed.
 #[no_mangle]
-pub extern "C" fn init_settings() {
-    let file_path = "sjq.toml";
-    init_settings_with_file_(file_path);
+pub extern "C" fn init_settings() -> bool {
+    init_settings_with_file(c"sjq.toml".as_ptr())
 }
 
-/// Sets the global settings to the provided `Settings` instance.
-fn set_settings(table: Settings) {
+/// Sets the global settings to the provided settings file.
+fn set_settings(file: SettingsFile) {
     let mut settings = SETTINGS.lock().unwrap();
-    info!("set_settings: {:?}", table);
-    *settings = Some(table);
+    info!("set_settings: {:?}", file);
+    *settings = Some(file);
+}
+
+/// Reads a table of the current settings file, or returns `None` if the settings have not been
+/// initialized.
+fn with_settings_file<T>(read: impl FnOnce(&SettingsFile) -> T) -> Option<T> {
+    SETTINGS.lock().unwrap().as_ref().map(read)
+}
+
+/// Returns a copy of the current settings, or `None` if they have not been initialized.
+pub fn current_settings() -> Option<Settings> {
+    with_settings_file(|file| file.settings.clone())
+}
+
+/// Returns the whole settings file as loaded, or `None` if the settings have not been
+/// initialized.
+pub fn current_settings_file() -> Option<SettingsFile> {
+    with_settings_file(SettingsFile::clone)
+}
+
+/// Returns the event filter of the settings file, keeping every event if none was loaded.
+pub fn current_event_filter() -> EventFilter {
+    with_settings_file(|file| file.event_log.clone()).unwrap_or_default()
+}
+
+/// Returns the histogram strategy of the settings file, exact buckets if none was loaded.
+pub fn current_histogram_strategy() -> HistogramStrategy {
+    with_settings_file(|file| file.histogram).unwrap_or_default()
+}
+
+/// Returns the sampling settings of the settings file, sampling disabled if none was loaded.
+pub fn current_sampling() -> SamplingSettings {
+    with_settings_file(|file| file.sampling.clone()).unwrap_or_default()
+}
+
+/// Returns the energy table of the settings file, the default estimates if none was loaded.
+pub fn current_energy_table() -> EnergyTable {
+    with_settings_file(|file| file.energy).unwrap_or_default()
 }
 
 /// Retrieves the current settings as a pointer to a `Settings` instance.
@@ -57,7 +144,28 @@ fn set_settings(table: Settings) {
 pub extern "C" fn get_settings() -> *const Settings {
     let settings = SETTINGS.lock().unwrap();
     match &*settings {
-        Some(settings) => settings,
+        Some(file) => &file.settings,
         None => std::ptr::null(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_table_is_an_error() {
+        let dir = crate::test_dir("invalid_settings_table");
+        let path = dir.join("sjq.toml");
+        let settings = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../sjq.toml"),
+        )
+        .unwrap()
+        .replace(r#"kind = "exact""#, "kind = \"log2\"\nsub_buckets = 0");
+        std::fs::write(&path, settings).unwrap();
+        let err = init_settings_with_file_(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::Toml { .. }), "{err}");
+        assert!(err.to_string().contains("power of two"), "{err}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
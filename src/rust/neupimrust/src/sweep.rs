@@ -0,0 +1,491 @@
+//! Design-space sweeps over [`SimulationConfig`] fields.
+//!
+//! A sweep spec is a TOML (or JSON) file naming the base config files and a set of axes:
+//!
+//! ```toml
+//! exclude = [{ n_tp = 8, sub_batch_mode = true }]
+//!
+//! [base]
+//! config = "../configs/systolic_ws_128x128_dev.json"
+//! mem_config = "../configs/memory_configs/neupims.json"
+//! sys_config = "../configs/system_configs/sub-batch-off.json"
+//!
+//! [axes]
+//! max_batch_size = { start = 32, end = 256, factor = 2 }
+//! dram_channels = { start = 16, end = 32, step = 16 }
+//! n_tp = [1, 2, 4, 8]
+//! sub_batch_mode = [true, false]
+//! model_config = ["../configs/model_configs/gpt3-7B.json", "../configs/model_configs/gpt3-13B.json"]
+//! ```
+//!
+//! An axis is either a config field or one of the config file slots of [`ConfigFiles`], in which
+//! case its values are paths. Paths are relative to the spec file. The sweep expands into the
+//! Cartesian product of all axes minus the `exclude` entries, and every point is materialized as a
+//! fully resolved `config.json` in its own directory of the run directory.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::{
+    config_loader::{ConfigBuilder, ConfigError, ConfigFiles, ConfigSource},
+    global_config::SimulationConfig,
+    provenance::fingerprint,
+};
+
+/// Axes that select a config file instead of setting a field.
+const FILE_AXES: &[&str] = &[
+    "config",
+    "mem_config",
+    "model_config",
+    "sys_config",
+    "cli_config",
+];
+
+/// The values taken by one axis.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum AxisValues {
+    List(Vec<Value>),
+    Range(RangeSpec),
+}
+
+/// An inclusive integer range, either arithmetic (`step`) or geometric (`factor`).
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RangeSpec {
+    pub start: u64,
+    pub end: u64,
+    pub step: Option<u64>,
+    pub factor: Option<u64>,
+}
+
+impl AxisValues {
+    fn expand(&self, axis: &str) -> Result<Vec<Value>, SweepError> {
+        let invalid = |message: &str| SweepError::InvalidAxis {
+            axis: axis.to_string(),
+            message: message.to_string(),
+        };
+        let values = match self {
+            AxisValues::List(values) => values.clone(),
+            AxisValues::Range(RangeSpec {
+                start,
+                end,
+                step,
+                factor,
+            }) => {
+                // Checked, so that a range ending near `u64::MAX` stops instead of wrapping.
+                let next: Box<dyn Fn(u64) -> Option<u64>> = match (step, factor) {
+                    (Some(0), None) => return Err(invalid("`step` must be positive")),
+                    (Some(step), None) => Box::new(move |v| v.checked_add(*step)),
+                    (None, Some(factor)) if *factor <= 1 => {
+                        return Err(invalid("`factor` must be above 1"))
+                    }
+                    (None, Some(_)) if *start == 0 => {
+                        return Err(invalid("a range with a `factor` needs a positive `start`"))
+                    }
+                    (None, Some(factor)) => Box::new(move |v| v.checked_mul(*factor)),
+                    (None, None) => Box::new(|v| v.checked_add(1)),
+                    (Some(_), Some(_)) => {
+                        return Err(invalid("a range takes either `step` or `factor`, not both"))
+                    }
+                };
+                std::iter::successors(Some(*start), |v| next(*v))
+                    .take_while(|v| v <= end)
+                    .map(Value::from)
+                    .collect()
+            }
+        };
+        if values.is_empty() {
+            return Err(invalid("the axis has no values"));
+        }
+        Ok(values)
+    }
+}
+
+/// A parsed sweep spec file.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SweepSpec {
+    /// The config files shared by every point.
+    #[serde(default)]
+    pub base: ConfigFiles,
+    pub axes: BTreeMap<String, AxisValues>,
+    /// Points matching all fields of any entry are left out of the sweep.
+    #[serde(default)]
+    pub exclude: Vec<Map<String, Value>>,
+    /// Whether points failing [`SimulationConfig::validate`] are left out of the run directory.
+    #[serde(default = "default_skip_invalid")]
+    pub skip_invalid: bool,
+}
+
+fn default_skip_invalid() -> bool {
+    true
+}
+
+/// One combination of axis values.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SweepPoint {
+    pub index: usize,
+    pub values: BTreeMap<String, Value>,
+}
+
+impl SweepPoint {
+    /// The directory name of the point inside the run directory.
+    pub fn dir_name(&self) -> String {
+        format!("point-{:04}", self.index)
+    }
+}
+
+/// The outcome of materializing one point, as recorded in `sweep.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepPointResult {
+    #[serde(flatten)]
+    pub point: SweepPoint,
+    /// The point's directory, or `None` if it was skipped.
+    pub dir: Option<PathBuf>,
+    /// Fingerprint of the resolved config, see [`fingerprint`].
+    pub config_hash: String,
+    /// Validation errors of the resolved config.
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum SweepError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Spec {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidAxis {
+        axis: String,
+        message: String,
+    },
+    Config {
+        point: usize,
+        source: Box<ConfigError>,
+    },
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            SweepError::Spec { path, message } => {
+                write!(f, "invalid sweep spec {}: {message}", path.display())
+            }
+            SweepError::InvalidAxis { axis, message } => {
+                write!(f, "invalid sweep axis `{axis}`: {message}")
+            }
+            SweepError::Config { point, source } => write!(f, "sweep point {point}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SweepError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SweepError::Io { source, .. } => Some(source),
+            SweepError::Config { source, .. } => Some(source.as_ref()),
+            SweepError::Spec { .. } | SweepError::InvalidAxis { .. } => None,
+        }
+    }
+}
+
+impl SweepSpec {
+    /// Reads a spec file. Relative paths in it are resolved against the file's directory.
+    pub fn from_file(path: &Path) -> Result<Self, SweepError> {
+        let content = std::fs::read_to_string(path).map_err(|source| SweepError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let spec_error = |message: String| SweepError::Spec {
+            path: path.to_owned(),
+            message,
+        };
+        let mut spec: SweepSpec = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&content).map_err(|err| spec_error(err.to_string()))?
+        } else {
+            toml::from_str(&content).map_err(|err| spec_error(err.to_string()))?
+        };
+        let dir = path.parent().unwrap_or(Path::new(""));
+        spec.resolve_paths(dir);
+        spec.check_exclude()?;
+        Ok(spec)
+    }
+
+    /// Checks that every `exclude` entry only names axes of the sweep, as an entry naming any
+    /// other key would never match and silently exclude nothing.
+    fn check_exclude(&self) -> Result<(), SweepError> {
+        for axis in self.exclude.iter().flat_map(Map::keys) {
+            if !self.axes.contains_key(axis) {
+                return Err(SweepError::InvalidAxis {
+                    axis: axis.clone(),
+                    message: "excluded, but not an axis of the sweep".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        let base = &mut self.base;
+        for path in [
+            &mut base.config,
+            &mut base.mem_config,
+            &mut base.model_config,
+            &mut base.sys_config,
+            &mut base.cli_config,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(&mut base.overrides)
+        {
+            *path = dir.join(&*path);
+        }
+        for (axis, values) in &mut self.axes {
+            if let (true, AxisValues::List(values)) = (FILE_AXES.contains(&axis.as_str()), values) {
+                for value in values {
+                    if let Value::String(path) = value {
+                        *path = dir.join(&*path).to_string_lossy().into_owned();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Expands the axes into their Cartesian product, leaving out excluded points.
+    ///
+    /// Points are numbered in expansion order, before exclusion, so a point keeps its index when
+    /// the exclusion list changes.
+    pub fn points(&self) -> Result<Vec<SweepPoint>, SweepError> {
+        self.check_exclude()?;
+        let fields = SimulationConfig::field_names();
+        let mut combinations = vec![BTreeMap::new()];
+        for (axis, values) in &self.axes {
+            if !FILE_AXES.contains(&axis.as_str()) && !fields.contains(axis) {
+                return Err(SweepError::InvalidAxis {
+                    axis: axis.clone(),
+                    message: "not a config field or config file".to_string(),
+                });
+            }
+            let values = values.expand(axis)?;
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.insert(axis.clone(), value.clone());
+                        combination
+                    })
+                })
+                .collect();
+        }
+        Ok(combinations
+            .into_iter()
+            .enumerate()
+            .map(|(index, values)| SweepPoint { index, values })
+            .filter(|point| !self.is_excluded(point))
+            .collect())
+    }
+
+    fn is_excluded(&self, point: &SweepPoint) -> bool {
+        self.exclude.iter().any(|exclude| {
+            exclude
+                .iter()
+                .all(|(axis, value)| point.values.get(axis) == Some(value))
+        })
+    }
+
+    /// Resolves the full config of `point`.
+    pub fn resolve(&self, point: &SweepPoint) -> Result<SimulationConfig, ConfigError> {
+        let mut files = self.base.clone();
+        let mut fields = vec![];
+        for (axis, value) in &point.values {
+            let slot = match axis.as_str() {
+                "config" => &mut files.config,
+                "mem_config" => &mut files.mem_config,
+                "model_config" => &mut files.model_config,
+                "sys_config" => &mut files.sys_config,
+                "cli_config" => &mut files.cli_config,
+                _ => {
+                    fields.push((axis, value));
+                    continue;
+                }
+            };
+            *slot = value.as_str().map(PathBuf::from);
+        }
+        let mut builder = ConfigBuilder::from_files(&files)?;
+        for (field, value) in fields {
+            builder.set(field.clone(), value.clone(), ConfigSource::Sweep);
+        }
+        Ok(builder.build()?.config)
+    }
+
+    /// Writes one `config.json` per point below `out_dir`, plus a `sweep.json` manifest
+    /// describing every point.
+    pub fn materialize(&self, out_dir: &Path) -> Result<Vec<SweepPointResult>, SweepError> {
+        let io_error = |path: &Path| {
+            let path = path.to_owned();
+            move |source| SweepError::Io { path, source }
+        };
+        let mut results = vec![];
+        for point in self.points()? {
+            let config = self.resolve(&point).map_err(|source| SweepError::Config {
+                point: point.index,
+                source: Box::new(source),
+            })?;
+            let errors: Vec<String> = match config.validate() {
+                Ok(()) => vec![],
+                Err(errors) => errors.iter().map(ToString::to_string).collect(),
+            };
+            let dir = if errors.is_empty() || !self.skip_invalid {
+                let dir = out_dir.join(point.dir_name());
+                std::fs::create_dir_all(&dir).map_err(io_error(&dir))?;
+                let path = dir.join("config.json");
+                let file = File::create(&path).map_err(io_error(&path))?;
+                serde_json::to_writer_pretty(file, &config).expect("config is serializable");
+                Some(dir)
+            } else {
+                warn!(
+                    "skipping invalid sweep point {}: {}",
+                    point.index,
+                    errors.join("; ")
+                );
+                None
+            };
+            results.push(SweepPointResult {
+                point,
+                dir,
+                config_hash: fingerprint(&config),
+                errors,
+            });
+        }
+
+        std::fs::create_dir_all(out_dir).map_err(io_error(out_dir))?;
+        let manifest = out_dir.join("sweep.json");
+        let file = File::create(&manifest).map_err(io_error(&manifest))?;
+        serde_json::to_writer_pretty(file, &results).expect("sweep results are serializable");
+        info!(
+            "materialized {} of {} sweep points into {}",
+            results.iter().filter(|r| r.dir.is_some()).count(),
+            results.len(),
+            out_dir.display()
+        );
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(toml: &str) -> SweepSpec {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_cartesian_product_with_exclude() {
+        let spec = spec(
+            r#"
+            exclude = [{ n_tp = 4, sub_batch_mode = true }]
+            [axes]
+            max_batch_size = { start = 32, end = 128, factor = 2 }
+            n_tp = [2, 4]
+            sub_batch_mode = [true, false]
+            "#,
+        );
+        let points = spec.points().unwrap();
+        assert_eq!(points.len(), 3 * 2 * 2 - 3);
+        assert!(points
+            .iter()
+            .all(|p| !(p.values["n_tp"] == 4 && p.values["sub_batch_mode"] == Value::Bool(true))));
+        assert_eq!(points.last().unwrap().index, 11);
+    }
+
+    #[test]
+    fn test_exclude_of_unknown_axis() {
+        let spec = spec(
+            r#"
+            exclude = [{ n_tp = 4, sub_batch_mod = true }]
+            [axes]
+            n_tp = [2, 4]
+            sub_batch_mode = [true, false]
+            "#,
+        );
+        assert!(matches!(
+            spec.points(),
+            Err(SweepError::InvalidAxis { axis, .. }) if axis == "sub_batch_mod"
+        ));
+    }
+
+    #[test]
+    fn test_unknown_axis() {
+        let spec = spec("[axes]\nmax_batch_sise = [1]");
+        assert!(matches!(
+            spec.points(),
+            Err(SweepError::InvalidAxis { axis, .. }) if axis == "max_batch_sise"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_and_overflowing_ranges() {
+        for range in [
+            "{ start = 1, end = 8, step = 0 }",
+            "{ start = 1, end = 8, factor = 1 }",
+            "{ start = 0, end = 8, factor = 2 }",
+            "{ start = 1, end = 8, step = 1, factor = 2 }",
+        ] {
+            let spec = spec(&format!("[axes]\nmax_batch_size = {range}"));
+            assert!(
+                matches!(spec.points(), Err(SweepError::InvalidAxis { .. })),
+                "{range} was accepted"
+            );
+        }
+
+        let near_max = |step, factor| {
+            AxisValues::Range(RangeSpec {
+                start: u64::MAX - 2,
+                end: u64::MAX,
+                step,
+                factor,
+            })
+            .expand("max_batch_size")
+            .unwrap()
+        };
+        assert_eq!(near_max(Some(2), None).len(), 2);
+        assert_eq!(near_max(None, Some(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_sets_fields() {
+        let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../configs");
+        let spec = SweepSpec {
+            base: ConfigFiles {
+                config: Some(configs.join("systolic_ws_128x128_dev.json")),
+                mem_config: Some(configs.join("memory_configs/neupims.json")),
+                model_config: Some(configs.join("model_configs/gpt3-7B.json")),
+                sys_config: Some(configs.join("system_configs/sub-batch-on.json")),
+                ..Default::default()
+            },
+            ..spec("[axes]\ndram_channels = { start = 16, end = 32, step = 16 }")
+        };
+        let points = spec.points().unwrap();
+        let channels: Vec<_> = points
+            .iter()
+            .map(|p| spec.resolve(p).unwrap().dram_channels)
+            .collect();
+        assert_eq!(channels, vec![16, 32]);
+    }
+}
@@ -16,7 +16,7 @@ pub struct Tensor {
 impl Tensor {
     pub fn new(dims: &[usize], tensor_type: TensorType) -> Self {
         let size = dims.iter().product();
-        
+
         Tensor {
             base_addr: 0,
             shape: dims.into(),
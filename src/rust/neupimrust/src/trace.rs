@@ -0,0 +1,261 @@
+//! Export of the simulation event stream in the Chrome Trace Event format.
+//!
+//! The resulting `trace.json` opens in Perfetto or `chrome://tracing`. Every run stage, every
+//! [`MemOp`] and the NPU and PIM each get their own track, so the interleaving of sub-batches
+//! between stages A–F can be inspected visually. Timestamps are core cycles: one microsecond in
+//! the viewer is one cycle.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tracing::{error, warn};
+
+use crate::{
+    event_log::read_events,
+    global_counts::{Event, EventType, GlobalCountsCtx, MemOp, RunStage},
+    provenance::RunInfo,
+};
+
+/// The only process of the trace.
+const PID: u32 = 1;
+
+/// A row of the trace viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Track {
+    Stage(RunStage),
+    Resource(MemOp),
+    Npu,
+    Pim,
+}
+
+impl Track {
+    /// The thread id of the track, which also orders the tracks in the viewer.
+    fn tid(self) -> u64 {
+        match self {
+            Track::Stage(stage) => stage as u64 + 1,
+            Track::Resource(op) => op as u64 + 100,
+            Track::Npu => 200,
+            Track::Pim => 201,
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Track::Stage(stage) => format!("Stage {stage:?}"),
+            Track::Resource(op) => format!("{op:?}"),
+            Track::Npu => "NPU".to_string(),
+            Track::Pim => "PIM".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Phase {
+    #[serde(rename = "B")]
+    Begin,
+    #[serde(rename = "E")]
+    End,
+    #[serde(rename = "M")]
+    Metadata,
+}
+
+/// One entry of `traceEvents`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+    pub name: String,
+    pub ph: Phase,
+    pub ts: u64,
+    pub pid: u32,
+    pub tid: u64,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub args: Map<String, Value>,
+}
+
+impl TraceEvent {
+    /// A metadata event setting `key` of the thread `tid`, or of the process if `tid` is 0.
+    fn metadata(tid: u64, name: &str, key: &str, value: Value) -> Self {
+        TraceEvent {
+            name: name.to_string(),
+            ph: Phase::Metadata,
+            ts: 0,
+            pid: PID,
+            tid,
+            args: Map::from_iter([(key.to_string(), value)]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChromeTrace {
+    pub trace_events: Vec<TraceEvent>,
+    /// Metadata of the whole trace. The simulator records its [`RunInfo`] as `run_info`.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub other_data: Map<String, Value>,
+}
+
+impl ChromeTrace {
+    /// Converts the event stream into a trace, naming every track that has events.
+    pub fn from_events(events: &[Event]) -> Self {
+        let mut tracks = vec![];
+        let mut trace_events = vec![];
+        for event in events {
+            let (track, ph) = match event.event {
+                EventType::MemEventStart(op) => (Track::Resource(op), Phase::Begin),
+                EventType::MemEventEnd(op) => (Track::Resource(op), Phase::End),
+                EventType::StageStart => (Track::Stage(event.stage), Phase::Begin),
+                EventType::StageEnd => (Track::Stage(event.stage), Phase::End),
+                EventType::NpuStart => (Track::Npu, Phase::Begin),
+                EventType::NpuFinished => (Track::Npu, Phase::End),
+                EventType::PimStart => (Track::Pim, Phase::Begin),
+                EventType::PimFinished => (Track::Pim, Phase::End),
+            };
+            if !tracks.contains(&track) {
+                tracks.push(track);
+            }
+            let name = match track {
+                Track::Stage(stage) => format!("{stage:?}"),
+                _ => track.name(),
+            };
+            let mut args = Map::new();
+            args.insert("stage".to_string(), json!(event.stage));
+            trace_events.push(TraceEvent {
+                name,
+                ph,
+                ts: event.cycle,
+                pid: PID,
+                tid: track.tid(),
+                args,
+            });
+        }
+
+        tracks.sort();
+        let mut metadata = vec![TraceEvent::metadata(
+            0,
+            "process_name",
+            "name",
+            json!("NeuPIMs"),
+        )];
+        for track in tracks {
+            let tid = track.tid();
+            metadata.push(TraceEvent::metadata(
+                tid,
+                "thread_name",
+                "name",
+                json!(track.name()),
+            ));
+            metadata.push(TraceEvent::metadata(
+                tid,
+                "thread_sort_index",
+                "sort_index",
+                json!(tid),
+            ));
+        }
+        metadata.extend(trace_events);
+        ChromeTrace {
+            trace_events: metadata,
+            other_data: Map::new(),
+        }
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+}
+
+/// Reads an `events.jsonl` and converts it into a trace.
+///
+/// A `counts.json` from before events were streamed, with an inline `event_vec`, is accepted as
+/// well.
+pub fn trace_from_file(path: &Path) -> Result<ChromeTrace, Box<dyn std::error::Error>> {
+    if path.extension().is_some_and(|ext| ext == "jsonl") {
+        return Ok(ChromeTrace::from_events(&read_events(path)?));
+    }
+    let mut counts: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let events = counts
+        .get_mut("event_vec")
+        .map(Value::take)
+        .ok_or_else(|| format!("{} has no `event_vec`", path.display()))?;
+    let events: Vec<Event> = serde_json::from_value(events)?;
+    Ok(ChromeTrace::from_events(&events))
+}
+
+/// 将事件流以 Chrome Trace 格式保存到输出目录下的 `trace.json`，[`RunInfo`] 记录在 `otherData` 中
+///
+/// 只包含未被 `[event_log]` 过滤的事件，有事件被过滤时会给出警告
+///
+/// # 返回值
+///
+/// 如果写入成功，返回`true`
+#[no_mangle]
+pub extern "C" fn save_trace_to_file(ctx: &mut GlobalCountsCtx) -> bool {
+    let path = ctx.out_dir.join("trace.json");
+    if ctx.events.filtered() > 0 {
+        warn!(
+            "{}中缺少被 [event_log] 过滤掉的{}个事件",
+            path.display(),
+            ctx.events.filtered()
+        );
+    }
+    let result = ctx
+        .events
+        .flush()
+        .and_then(|()| ctx.events.events())
+        .and_then(|events| {
+            let mut trace = ChromeTrace::from_events(&events);
+            let run_info = serde_json::to_value(RunInfo::current())?;
+            trace.other_data.insert("run_info".to_string(), run_info);
+            trace.write_to_file(&path)
+        });
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            error!("无法写入{}: {err}", path.display());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(cycle: u64, stage: RunStage, event: EventType) -> Event {
+        Event {
+            cycle,
+            stage,
+            event,
+        }
+    }
+
+    #[test]
+    fn test_tracks() {
+        let events = [
+            event(0, RunStage::A, EventType::StageStart),
+            event(5, RunStage::A, EventType::MemEventStart(MemOp::Load)),
+            event(9, RunStage::A, EventType::MemEventEnd(MemOp::Load)),
+            event(10, RunStage::B, EventType::StageStart),
+            event(12, RunStage::A, EventType::StageEnd),
+            event(20, RunStage::B, EventType::NpuFinished),
+        ];
+        let trace = ChromeTrace::from_events(&events);
+        let names: Vec<_> = trace
+            .trace_events
+            .iter()
+            .filter(|event| event.name == "thread_name")
+            .map(|event| event.args["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Stage A", "Stage B", "Load", "NPU"]);
+
+        let value = serde_json::to_value(&trace).unwrap();
+        let stage_b = &value["traceEvents"][1 + 2 * names.len() + 3];
+        assert_eq!(
+            stage_b,
+            &json!({ "name": "B", "ph": "B", "ts": 10, "pid": 1, "tid": 2, "args": { "stage": "B" } })
+        );
+        assert!(value.get("otherData").is_none());
+    }
+}
@@ -0,0 +1,264 @@
+//! Unit-suffixed config values such as `"32GiB"` or `"1GHz"`.
+//!
+//! Sizes and frequencies are stored as integers in a fixed canonical unit, named in the field's
+//! documentation and schema. Config files may give either that integer or a string with a unit
+//! suffix, which is converted to the canonical unit when the config is deserialized. A value that
+//! is not a whole number of canonical units, e.g. `"1500B"` for a field in KiB, is rejected
+//! instead of rounded. Suffixes are case-sensitive, as `mb` could mean either `MB` or `MiB`.
+
+use std::fmt;
+
+use schemars::{gen::SchemaGenerator, schema::Schema};
+use serde::{de, Deserializer};
+
+/// The canonical unit of a config field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Bytes,
+    KiB,
+    MHz,
+}
+
+/// Size suffixes in bytes. `KB`/`MB`/`GB` are decimal, `KiB`/`MiB`/`GiB` binary.
+const SIZE_SUFFIXES: &[(&str, u64)] = &[
+    ("B", 1),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+];
+
+/// Frequency suffixes in Hz.
+const FREQUENCY_SUFFIXES: &[(&str, u64)] = &[
+    ("Hz", 1),
+    ("kHz", 1_000),
+    ("MHz", 1_000_000),
+    ("GHz", 1_000_000_000),
+];
+
+impl Unit {
+    /// The suffix of the unit, as accepted by [`Unit::parse`].
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Bytes => "B",
+            Unit::KiB => "KiB",
+            Unit::MHz => "MHz",
+        }
+    }
+
+    /// The suffixes of the unit's dimension and the size of one canonical unit in their base.
+    fn dimension(self) -> (&'static [(&'static str, u64)], u64) {
+        match self {
+            Unit::Bytes => (SIZE_SUFFIXES, 1),
+            Unit::KiB => (SIZE_SUFFIXES, 1 << 10),
+            Unit::MHz => (FREQUENCY_SUFFIXES, 1_000_000),
+        }
+    }
+
+    /// Parses `value` into a whole number of this unit. A bare number is taken as already being
+    /// in this unit.
+    pub fn parse(self, value: &str) -> Result<u64, UnitError> {
+        let error = |message: String| UnitError {
+            value: value.to_string(),
+            unit: self,
+            message,
+        };
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '_'))
+            .unwrap_or(value.len());
+        let (number, suffix) = value.split_at(split);
+        // Keep the number as an exact decimal `mantissa / 10^decimals`.
+        let number = number.replace('_', "");
+        let (integer, fraction) = number.split_once('.').unwrap_or((&number, ""));
+        let mantissa: u128 = format!("{integer}{fraction}")
+            .parse()
+            .map_err(|_| error("expected a number followed by a unit".to_string()))?;
+        let decimals = 10u128
+            .checked_pow(fraction.len() as u32)
+            .ok_or_else(|| error("too many decimal places".to_string()))?;
+
+        let (suffixes, canonical) = self.dimension();
+        let scale = match suffix.trim() {
+            "" => canonical,
+            suffix => {
+                suffixes
+                    .iter()
+                    .find(|(symbol, _)| *symbol == suffix)
+                    .ok_or_else(|| {
+                        let valid: Vec<_> = suffixes.iter().map(|(symbol, _)| *symbol).collect();
+                        error(format!(
+                            "unknown unit `{suffix}`, expected one of {}",
+                            valid.join(", ")
+                        ))
+                    })?
+                    .1
+            }
+        };
+        let base = mantissa
+            .checked_mul(scale as u128)
+            .ok_or_else(|| error("value is too large".to_string()))?;
+        let divisor = decimals * canonical as u128;
+        if base % divisor != 0 {
+            return Err(error(format!("not a whole number of {}", self.symbol())));
+        }
+        u64::try_from(base / divisor).map_err(|_| error("value is too large".to_string()))
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// A config value that could not be converted to its field's unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitError {
+    pub value: String,
+    pub unit: Unit,
+    pub message: String,
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid value `{}` for a field in {}: {}",
+            self.value, self.unit, self.message
+        )
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+struct QuantityVisitor(Unit);
+
+impl de::Visitor<'_> for QuantityVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an integer in {} or a string with a unit suffix", self.0)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        u64::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        self.0.parse(v).map_err(E::custom)
+    }
+}
+
+fn quantity<'de, D, T>(deserializer: D, unit: Unit) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    let value = deserializer.deserialize_any(QuantityVisitor(unit))?;
+    T::try_from(value)
+        .map_err(|_| de::Error::custom(format!("{value} {unit} is out of range for the field")))
+}
+
+/// Deserializes a size in bytes, for `#[serde(deserialize_with = "units::bytes")]`.
+pub fn bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    quantity(deserializer, Unit::Bytes)
+}
+
+/// Deserializes a size in KiB.
+pub fn kib<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    quantity(deserializer, Unit::KiB)
+}
+
+/// Deserializes a frequency in MHz.
+pub fn mhz<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    quantity(deserializer, Unit::MHz)
+}
+
+fn quantity_schema(unit: Unit) -> Schema {
+    let (suffixes, _) = unit.dimension();
+    let suffixes: Vec<_> = suffixes.iter().map(|(symbol, _)| *symbol).collect();
+    serde_json::from_value(serde_json::json!({
+        "anyOf": [
+            { "type": "integer", "minimum": 0 },
+            {
+                "type": "string",
+                "pattern": format!(r"^\s*[0-9_]+(\.[0-9_]*)?\s*({})?\s*$", suffixes.join("|")),
+            },
+        ],
+        "x-unit": unit.symbol(),
+    }))
+    .expect("a valid schema")
+}
+
+/// The schema of a size in bytes, for `#[schemars(schema_with = "units::bytes_schema")]`.
+pub fn bytes_schema(_: &mut SchemaGenerator) -> Schema {
+    quantity_schema(Unit::Bytes)
+}
+
+pub fn kib_schema(_: &mut SchemaGenerator) -> Schema {
+    quantity_schema(Unit::KiB)
+}
+
+pub fn mhz_schema(_: &mut SchemaGenerator) -> Schema {
+    quantity_schema(Unit::MHz)
+}
+
+/// Formats a size in bytes with the largest binary unit that keeps it at least 1, e.g.
+/// `1.50 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    let (symbol, scale) = SIZE_SUFFIXES
+        .iter()
+        .rev()
+        .find(|(symbol, scale)| (*symbol == "B" || symbol.ends_with("iB")) && bytes >= *scale)
+        .copied()
+        .unwrap_or(("B", 1));
+    if scale == 1 {
+        format!("{bytes} B")
+    } else {
+        format!("{:.2} {symbol}", bytes as f64 / scale as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(Unit::Bytes.parse("32GiB"), Ok(32 << 30));
+        assert_eq!(Unit::Bytes.parse("512 MiB"), Ok(512 << 20));
+        assert_eq!(Unit::Bytes.parse("4096"), Ok(4096));
+        assert_eq!(Unit::KiB.parse("7MiB"), Ok(7 << 10));
+        assert_eq!(Unit::MHz.parse("1GHz"), Ok(1000));
+        assert_eq!(Unit::MHz.parse("1.2GHz"), Ok(1200));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(1000), "1000 B");
+        assert_eq!(format_bytes(3 << 29), "1.50 GiB");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Unit::KiB.parse("1500B").unwrap_err();
+        assert_eq!(err.message, "not a whole number of KiB");
+        let err = Unit::MHz.parse("1GiB").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value `1GiB` for a field in MHz: unknown unit `GiB`, expected one of Hz, kHz, MHz, GHz"
+        );
+        assert!(Unit::Bytes.parse("lots").is_err());
+        let err = Unit::Bytes.parse("1mb").unwrap_err();
+        assert!(err.message.starts_with("unknown unit `mb`"), "{err}");
+    }
+}
@@ -1,7 +1,11 @@
 use neupimrust::{init_logger, LogLevel};
+use tracing::error;
 fn main() {
     init_logger(LogLevel::Info);
-    neupimrust::run();
+    if let Err(err) = neupimrust::run() {
+        error!("{err}");
+        std::process::exit(1);
+    }
 }
 
 #[cfg(test)]
@@ -17,6 +17,7 @@ Scheduler::Scheduler(SimulationConfig config, const cycle_type* core_cycle)
     _nh = _config.model_n_head / _config.n_tp;
     _dk = _config.model_n_embd / _config.model_n_head;
     _effective_e = _nh * _dk;
+    _kv_effective_e = _config.model_n_kv_head / _config.n_tp * _dk;
 
     // Memory spec init
     _dram_channels = _config.dram_channels;
@@ -70,8 +71,8 @@ Scheduler::Scheduler(SimulationConfig config, const cycle_type* core_cycle)
     _value_period = _dram_page_size;
 
     // how many PIM tiles compose a page.
-    _key_page_size = ceil((double)_effective_e / _value_period);
-    _value_page_size = ceil((double)_effective_e / _key_period);
+    _key_page_size = ceil((double)_kv_effective_e / _value_period);
+    _value_page_size = ceil((double)_kv_effective_e / _key_period);
 
     spdlog::info("_key_period: {}", _key_period);
     spdlog::info("_key_page_size: {}", _key_page_size);
@@ -260,6 +261,11 @@ void Scheduler::make_program() {
     _model_program2 =
         std::make_unique<StageProgram>(_model, sub_batch_on_pim, StagePlatform::PIM, _stage);
 
+    // a program without operations (an empty sub-batch, or PIM outside the attention stages)
+    // finishes at once and must not show up as busy time
+    if (!_model_program1->_op_map.empty()) sjq_rust::npu_start(global_counts_ctx, *_core_cycle);
+    if (!_model_program2->_op_map.empty()) sjq_rust::pim_start(global_counts_ctx, *_core_cycle);
+
     refresh_status1();
     refresh_status2();
 }
@@ -414,6 +420,7 @@ void Scheduler::cycle() {
                 std::string red = "\033[1;31m";
                 std::string reset = "\033[0m";
                 spdlog::info("{}----------Stage {}----------{}", red, stageToString(_stage), reset);
+                sjq_rust::update_stage(global_counts_ctx, from_stage(_stage), *_core_cycle);
                 make_program();
             }
         }
@@ -627,6 +634,7 @@ void Scheduler::refresh_stage() {
 void Scheduler::finish_program1() {
     spdlog::info("Model finish at {}", *_core_cycle);
     _model_program1->log();
+    if (!_model_program1->_op_map.empty()) sjq_rust::npu_finished(global_counts_ctx, *_core_cycle);
 
     _model_program1 = nullptr;
     refresh_stage();
@@ -638,6 +646,7 @@ void Scheduler::finish_program1() {
 void Scheduler::finish_program2() {
     spdlog::info("Model finish at {}", *_core_cycle);
     _model_program2->log();
+    if (!_model_program2->_op_map.empty()) sjq_rust::pim_finished(global_counts_ctx, *_core_cycle);
 
     _model_program2 = nullptr;
     refresh_stage();
@@ -89,6 +89,7 @@ class Scheduler {
     uint32_t _nh;
     uint32_t _dk;
     uint32_t _effective_e;
+    uint32_t _kv_effective_e;  // _effective_e over the KV heads (GQA/MQA)
 
     // memory spec
     uint32_t _dram_channels;